@@ -0,0 +1,980 @@
+use serde::{ Deserialize, Serialize };
+use std::collections::{ HashMap, HashSet };
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::sync::mpsc;
+use std::sync::{ Arc, RwLock };
+use notify::{ Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher };
+use actix_web::{ HttpMessage, HttpRequest };
+use crate::utils::{ strip_port, wildcard_matches };
+use crate::utils::compression::{ default_preference, CompressionLevels, Encoding };
+use crate::utils::path_resolver::prefix_matches;
+
+// listener, ktery se zavola po kazdem uspesnem nacteni konfigurace
+type ReloadListener = Box<dyn Fn(&ServerConfig) + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub server_directory: String,
+    pub port: u16,
+    pub timeout: u64,
+    pub max_connections: usize,
+    pub bind_address: String,
+    pub cache_enabled: bool,
+    pub default_max_age: u32,
+    /// pamet pro cache hotovych odpovedi statickych a JS souboru (v bajtech), 0 = vypnuto
+    pub response_cache_size: usize,
+    /// jak se pocita ETag statickych souboru
+    pub etag: EtagMode,
+    pub development_mode: bool,
+    pub enable_compression: bool,
+    pub min_size_to_compress: usize,
+    /// poradi kodeku, kdyz klient prijima vic z nich se stejnou vahou
+    pub compression_preference: Vec<Encoding>,
+    pub compression_levels: CompressionLevels,
+    pub static_root: String,
+    /// volitelny limit velikosti posilanych souboru, soubory se streamuji
+    pub max_file_size: Option<usize>,
+    /// vypis adresaru ve static_root; obvykle se zapina jen pro jednu `[[location]]`
+    pub autoindex: bool,
+    /// soubory hledane v adresari v tomto poradi (static, PHP i koren vhostu)
+    pub index_files: Vec<String>,
+    /// dokument v static_root, ktery dostane HTML navigace na neexistujici cestu
+    /// (React, Vue); chybejici assety dal vraci 404
+    pub spa_fallback: Option<String>,
+    /// symlinky ve static_root: `off`, `owner_match` (odkaz a cil patri stejnemu
+    /// uzivateli) nebo `on`
+    pub follow_symlinks: SymlinkPolicy,
+    /// glob vzory (`*`, `?`) pro soubory, ktere se ze static_root neposilaji; vzor bez `/`
+    /// se porovnava s kazdou casti cesty
+    pub deny_patterns: Vec<String>,
+    pub js_minify: bool,
+    pub js_root_dir: String,
+    pub enable_websockets: bool,
+    pub websocket_path: String,
+    pub websocket_max_connections: usize,
+    pub websocket_timeout: u64,
+    pub custom_error_pages: bool,
+    pub php_enabled: bool,
+    pub php_cgi_path: String,
+    pub php_exe_path: String,
+    pub php_root_dir: String,
+    pub php_timeout: u64,
+    /// jak se spousti PHP: `spawn` (novy proces pro kazdy request, vychozi),
+    /// `pool` (vlastni php-cgi workery) nebo `fastcgi` (php-fpm)
+    pub php_mode: PhpMode,
+    /// adresa php-fpm: `127.0.0.1:9000` nebo `unix:/run/php/php-fpm.sock`
+    pub php_fastcgi_address: String,
+    /// maximalni pocet FastCGI spojeni na jeden worker
+    pub php_fastcgi_pool_size: usize,
+    /// minimalni a maximalni pocet php-cgi workeru v rezimu `pool`
+    pub php_pool_min: usize,
+    pub php_pool_max: usize,
+    /// po kolika requestech se worker recykluje (PHP_FCGI_MAX_REQUESTS), 0 = nikdy
+    pub php_pool_max_requests: u64,
+    /// jak dlouho muze request cekat na volny worker (v sekundach)
+    pub php_pool_queue_timeout: u64,
+    /// front controller frameworku (napr. `index.php`) - URL, ktere neodpovida
+    /// souboru ani adresari v `php_root_dir`, se preda tomuto scriptu
+    pub php_front_controller: Option<String>,
+    pub file_extension_cache_times: HashMap<String, u32>,
+    pub error_pages: HashMap<String, String>,
+    /// promenne prostredi navic pro PHP scripty (prepisuji i vychozi CGI promenne)
+    pub php_env: HashMap<String, String>,
+    /// adresy (nebo site `10.0.0.0/8`) proxy pred serverem, kterym se veri
+    /// `X-Forwarded-*` hlavicky; od ostatnich klientu se zahodi
+    pub trusted_proxies: Vec<String>,
+    pub tls: TlsConfig,
+    #[serde(rename = "vhost")]
+    pub vhosts: Vec<VirtualHost>,
+    #[serde(rename = "proxy")]
+    pub proxies: Vec<ProxyRule>,
+    #[serde(rename = "upstream")]
+    pub upstreams: Vec<UpstreamGroup>,
+    #[serde(rename = "rewrite")]
+    pub rewrites: Vec<RewriteRule>,
+    #[serde(rename = "location")]
+    pub locations: Vec<Location>,
+}
+
+/// `[[proxy]]` - requesty s danym prefixem se predavaji na upstream HTTP server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyRule {
+    pub prefix: String,
+    /// URL (`http://127.0.0.1:3000`) nebo nazev skupiny z `[[upstream]]`
+    pub upstream: String,
+    /// odstrani prefix z cesty predavane na upstream
+    pub strip_prefix: bool,
+    /// timeout celeho requestu na upstream (v sekundach)
+    pub timeout: u64,
+}
+
+/// `[[upstream]]` - skupina serveru, mezi ktere proxy rozklada requesty
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpstreamGroup {
+    pub name: String,
+    pub servers: Vec<String>,
+    pub strategy: BalanceStrategy,
+    /// cesta pro aktivni health check, bez ni se aktivni kontrola nedela
+    pub health_check_path: Option<String>,
+    /// interval aktivniho health checku (v sekundach)
+    pub health_check_interval: u64,
+    pub health_check_timeout: u64,
+    /// pocet chyb za sebou, po kterem je server vyrazen (pasivni kontrola)
+    pub max_fails: u32,
+    /// jak dlouho je vyrazeny server mimo provoz (v sekundach)
+    pub fail_timeout: u64,
+    /// stavove kody odpovedi, ktere se pri pasivni kontrole pocitaji jako chyba
+    pub fail_statuses: Vec<u16>,
+}
+
+/// `[[location]]` - namapovani cesty na handler; doplnuje vychozi tabulku
+/// (`/static/`, `/php/`, `/js/`, `[[proxy]]`, `websocket_path`), location se
+/// stejnou cestou a typem shody vychozi polozku nahradi
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Location {
+    /// prefix, presna cesta nebo regex podle `match`
+    pub path: String,
+    #[serde(rename = "match")]
+    pub match_kind: LocationMatch,
+    pub handler: LocationHandler,
+    /// adresar pro static/php/js (jinak static_root, php_root_dir, js_root_dir)
+    pub root: Option<String>,
+    /// `php_front_controller` jen pro tuto location
+    pub front_controller: Option<String>,
+    /// static: vypis adresare bez indexu (HTML nebo JSON)
+    pub autoindex: Option<bool>,
+    /// static: dokument single-page aplikace pro neznamou cestu (`index.html`)
+    pub spa_fallback: Option<String>,
+    /// proxy: URL nebo nazev skupiny z `[[upstream]]`
+    pub upstream: String,
+    pub strip_prefix: bool,
+    pub timeout: u64,
+    /// redirect: cilova URL (u regexu muze obsahovat `$1`)
+    pub target: String,
+    /// redirect (vychozi 302) a return (vychozi 200)
+    pub status: Option<u16>,
+    /// return: telo a typ odpovedi
+    pub body: String,
+    pub content_type: Option<String>,
+}
+
+/// poradi vyhodnoceni: `exact`, potom `regex` v poradi z konfigurace, potom nejdelsi `prefix`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationMatch {
+    Prefix,
+    Exact,
+    Regex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationHandler {
+    Static,
+    Php,
+    Js,
+    Proxy,
+    Websocket,
+    Redirect,
+    Return,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Location {
+            path: "/".to_string(),
+            match_kind: LocationMatch::Prefix,
+            handler: LocationHandler::Static,
+            root: None,
+            front_controller: None,
+            autoindex: None,
+            spa_fallback: None,
+            upstream: String::new(),
+            strip_prefix: false,
+            timeout: 30,
+            target: String::new(),
+            status: None,
+            body: String::new(),
+            content_type: None,
+        }
+    }
+}
+
+impl Location {
+    /// prepise koren a volby handleru v konfiguraci requestu
+    fn apply(&self, config: &mut ServerConfig) {
+        if let Some(root) = &self.root {
+            match self.handler {
+                LocationHandler::Static => {
+                    config.static_root = root.clone();
+                }
+                LocationHandler::Php => {
+                    config.php_root_dir = root.clone();
+                }
+                LocationHandler::Js => {
+                    config.js_root_dir = root.clone();
+                }
+                _ => {}
+            }
+        }
+
+        if self.front_controller.is_some() {
+            config.php_front_controller = self.front_controller.clone();
+        }
+
+        if let Some(autoindex) = self.autoindex {
+            config.autoindex = autoindex;
+        }
+
+        if self.spa_fallback.is_some() {
+            config.spa_fallback = self.spa_fallback.clone();
+        }
+    }
+
+    /// proxy nastaveni location ve tvaru `[[proxy]]` pravidla
+    pub fn proxy_rule(&self, prefix: &str) -> ProxyRule {
+        ProxyRule {
+            prefix: prefix.to_string(),
+            upstream: self.upstream.clone(),
+            strip_prefix: self.strip_prefix,
+            timeout: self.timeout,
+        }
+    }
+}
+
+/// location vybrana pro request, ulozena v extensions requestu
+#[derive(Debug, Clone)]
+pub struct MatchedLocation {
+    pub location: Arc<Location>,
+    /// cast URL pred `relative_path` (u prefixu samotny prefix)
+    pub url_prefix: String,
+    /// cesta, kterou handler hleda ve svem koreni - jeste nedekodovana,
+    /// handler ji prevadi pres `path_resolver::resolve`
+    pub relative_path: String,
+    /// skupiny z regexu pro `target` redirectu
+    pub captures: Vec<Option<String>>,
+}
+
+impl MatchedLocation {
+    pub fn of(req: &HttpRequest) -> Option<MatchedLocation> {
+        req.extensions().get::<MatchedLocation>().cloned()
+    }
+}
+
+/// `[[rewrite]]` - prepis nebo presmerovani URL pred routovanim
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewriteRule {
+    /// regex testovany proti ceste (bez query stringu)
+    pub pattern: String,
+    /// nova URL, `$1`/`${name}` se nahradi skupinami z `pattern`;
+    /// `?` v cilu nahradi puvodni query string (s `qsa` se k nemu pripoji)
+    pub target: String,
+    pub flags: Vec<RewriteFlag>,
+    /// vsechny podminky musi platit
+    pub conditions: Vec<RewriteCondition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewriteFlag {
+    /// po prepsani projde pravidla znovu od zacatku s novou URL
+    Last,
+    /// po prepsani uz dalsi pravidla nezkousi
+    Break,
+    /// pripoji puvodni query string k query stringu z `target`
+    Qsa,
+    #[serde(rename = "redirect_301")]
+    Redirect301,
+    #[serde(rename = "redirect_302")]
+    Redirect302,
+    #[serde(rename = "redirect_307")]
+    Redirect307,
+    #[serde(rename = "redirect_308")]
+    Redirect308,
+}
+
+/// podminka pravidla - nastavuje se prave jedna z `host`, `method`, `header`, `file_exists`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewriteCondition {
+    /// regex pro Host (bez portu)
+    pub host: Option<String>,
+    /// povolene HTTP metody
+    pub method: Option<Vec<String>>,
+    /// nazev hlavicky; bez `value` staci, ze hlavicka existuje
+    pub header: Option<String>,
+    /// regex pro hodnotu hlavicky `header`
+    pub value: Option<String>,
+    /// cesta k souboru v `root` (muze obsahovat `$1` z `pattern`), ktery musi existovat;
+    /// cesta mimo `root` (`..`, symlink ven) podminku nesplni
+    pub file_exists: Option<String>,
+    /// adresar pro `file_exists`, vychozi je `server_directory` hostu
+    pub root: Option<String>,
+    /// obrati vysledek podminky
+    pub negate: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    RoundRobin,
+    LeastConnections,
+    IpHash,
+}
+
+impl Default for UpstreamGroup {
+    fn default() -> Self {
+        UpstreamGroup {
+            name: String::new(),
+            servers: Vec::new(),
+            strategy: BalanceStrategy::RoundRobin,
+            health_check_path: None,
+            health_check_interval: 10,
+            health_check_timeout: 5,
+            max_fails: 3,
+            fail_timeout: 30,
+            fail_statuses: vec![502, 503, 504],
+        }
+    }
+}
+
+impl Default for ProxyRule {
+    fn default() -> Self {
+        ProxyRule {
+            prefix: "/".to_string(),
+            upstream: String::new(),
+            strip_prefix: false,
+            timeout: 30,
+        }
+    }
+}
+
+/// `[[vhost]]` - nastaveni, ktera prepisuji globalni hodnoty pro dany Host
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualHost {
+    /// presne nazvy nebo wildcardy ve tvaru `*.example.com`
+    pub server_names: Vec<String>,
+    /// pouzije se, kdyz zadny jiny vhost neodpovida
+    pub default: bool,
+    pub server_directory: Option<String>,
+    pub static_root: Option<String>,
+    pub php_root_dir: Option<String>,
+    pub js_root_dir: Option<String>,
+    pub custom_error_pages: Option<bool>,
+    pub error_pages: Option<HashMap<String, String>>,
+    pub enable_compression: Option<bool>,
+    pub min_size_to_compress: Option<usize>,
+    pub cache_enabled: Option<bool>,
+    pub default_max_age: Option<u32>,
+    pub file_extension_cache_times: Option<HashMap<String, u32>>,
+    pub etag: Option<EtagMode>,
+    pub index_files: Option<Vec<String>>,
+    pub follow_symlinks: Option<SymlinkPolicy>,
+    pub deny_patterns: Option<Vec<String>>,
+    pub php_enabled: Option<bool>,
+    pub php_cgi_path: Option<String>,
+    pub php_exe_path: Option<String>,
+    pub php_timeout: Option<u64>,
+    pub php_mode: Option<PhpMode>,
+    pub php_fastcgi_address: Option<String>,
+    pub php_front_controller: Option<String>,
+    /// doplni (pripadne prepise) globalni `php_env`
+    pub php_env: Option<HashMap<String, String>>,
+}
+
+impl VirtualHost {
+    fn apply(&self, config: &mut ServerConfig) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+
+        set(&mut config.server_directory, &self.server_directory);
+        set(&mut config.static_root, &self.static_root);
+        set(&mut config.php_root_dir, &self.php_root_dir);
+        set(&mut config.js_root_dir, &self.js_root_dir);
+        set(&mut config.custom_error_pages, &self.custom_error_pages);
+        set(&mut config.error_pages, &self.error_pages);
+        set(&mut config.enable_compression, &self.enable_compression);
+        set(&mut config.min_size_to_compress, &self.min_size_to_compress);
+        set(&mut config.cache_enabled, &self.cache_enabled);
+        set(&mut config.default_max_age, &self.default_max_age);
+        set(&mut config.file_extension_cache_times, &self.file_extension_cache_times);
+        set(&mut config.etag, &self.etag);
+        set(&mut config.index_files, &self.index_files);
+        set(&mut config.follow_symlinks, &self.follow_symlinks);
+        set(&mut config.deny_patterns, &self.deny_patterns);
+        set(&mut config.php_enabled, &self.php_enabled);
+        set(&mut config.php_cgi_path, &self.php_cgi_path);
+        set(&mut config.php_exe_path, &self.php_exe_path);
+        set(&mut config.php_timeout, &self.php_timeout);
+        set(&mut config.php_mode, &self.php_mode);
+        set(&mut config.php_fastcgi_address, &self.php_fastcgi_address);
+
+        if self.php_front_controller.is_some() {
+            config.php_front_controller = self.php_front_controller.clone();
+        }
+
+        if let Some(php_env) = &self.php_env {
+            config.php_env.extend(php_env.iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhpMode {
+    Spawn,
+    Fastcgi,
+    Pool,
+}
+
+/// `metadata` - silny ETag z velikosti, mtime a inode jako u nginxu (bez cteni souboru),
+/// `content` - SHA-256 obsahu, prepocita se jen po zmene mtime, `off` - bez ETagu;
+/// puvodni nazvy `weak` a `strong` zustavaji jako aliasy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EtagMode {
+    #[serde(alias = "weak")]
+    Metadata,
+    #[serde(alias = "strong")]
+    Content,
+    Off,
+}
+
+impl EtagMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EtagMode::Metadata => "metadata",
+            EtagMode::Content => "content",
+            EtagMode::Off => "off",
+        }
+    }
+}
+
+/// `off` - zadne symlinky pod korenem, `owner_match` - jen kdyz odkaz a cil
+/// vlastni stejny uzivatel (muze vest i mimo koren), `on` - vsechny
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    Off,
+    #[serde(alias = "owner-match", alias = "owner")]
+    OwnerMatch,
+    On,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    /// samostatny port pro HTTPS, bez nej bezi HTTPS na `port`
+    pub port: Option<u16>,
+    pub cert_path: String,
+    pub key_path: String,
+    /// "1.2" nebo "1.3"
+    pub min_version: String,
+    /// presmerovani HTTP -> HTTPS na `port` (jen se samostatnym TLS portem)
+    pub redirect_http: bool,
+    /// dalsi certifikaty vybirane podle SNI, `cert_path`/`key_path` je vychozi
+    pub certificates: Vec<TlsCertificate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsCertificate {
+    /// presne nazvy nebo wildcardy ve tvaru `*.example.com`
+    pub server_names: Vec<String>,
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            enabled: false,
+            port: None,
+            cert_path: "config/tls/cert.pem".to_string(),
+            key_path: "config/tls/key.pem".to_string(),
+            min_version: "1.2".to_string(),
+            redirect_http: false,
+            certificates: Vec::new(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let mut file_extension_cache_times = HashMap::new();
+        // casto menici se
+        file_extension_cache_times.insert("html".to_string(), 3600);
+        file_extension_cache_times.insert("htm".to_string(), 3600);
+
+        // stabilni
+        file_extension_cache_times.insert("css".to_string(), 604800);
+        file_extension_cache_times.insert("js".to_string(), 604800);
+        file_extension_cache_times.insert("woff2".to_string(), 2592000);
+        file_extension_cache_times.insert("woff".to_string(), 2592000);
+        file_extension_cache_times.insert("ttf".to_string(), 2592000);
+        file_extension_cache_times.insert("eot".to_string(), 2592000);
+        file_extension_cache_times.insert("otf".to_string(), 2592000);
+
+        // staticky
+        file_extension_cache_times.insert("jpg".to_string(), 31536000);
+        file_extension_cache_times.insert("jpeg".to_string(), 31536000);
+        file_extension_cache_times.insert("png".to_string(), 31536000);
+        file_extension_cache_times.insert("gif".to_string(), 31536000);
+        file_extension_cache_times.insert("ico".to_string(), 31536000);
+        file_extension_cache_times.insert("svg".to_string(), 31536000);
+        file_extension_cache_times.insert("webp".to_string(), 31536000);
+
+        let mut error_pages = HashMap::new();
+        for code in [
+            400, 401, 402, 403, 404, 405, 406, 407, 408, 409, 410, 411, 412, 413, 414, 415, 416,
+            417, 418, 421, 422, 423, 424, 425, 426, 428, 429, 431, 451, 500, 501, 502, 503, 504,
+            505, 506, 507, 508, 510, 511,
+        ] {
+            error_pages.insert(code.to_string(), format!("errors/{}.html", code));
+        }
+
+        ServerConfig {
+            server_directory: "server".to_string(),
+            port: 8080,
+            timeout: 30,
+            max_connections: 1000,
+            bind_address: "0.0.0.0".to_string(),
+            cache_enabled: true,
+            default_max_age: 86400,
+            response_cache_size: 64 * 1024 * 1024,
+            etag: EtagMode::Metadata,
+            development_mode: false,
+            enable_compression: true,
+            min_size_to_compress: 1024,
+            compression_preference: default_preference(),
+            compression_levels: CompressionLevels::default(),
+            static_root: "static".to_string(),
+            max_file_size: None,
+            autoindex: false,
+            index_files: vec!["index.php".to_string(), "index.html".to_string()],
+            spa_fallback: None,
+            follow_symlinks: SymlinkPolicy::OwnerMatch,
+            deny_patterns: vec![".*".to_string(), "*.bak".to_string(), "*.php".to_string()],
+            js_minify: true,
+            js_root_dir: "static/js".to_string(),
+            enable_websockets: false,
+            websocket_path: "/ws".to_string(),
+            websocket_max_connections: 1000,
+            websocket_timeout: 60,
+            custom_error_pages: false,
+            php_enabled: true,
+            php_cgi_path: "php/php-cgi.exe".to_string(),
+            php_exe_path: "php/php.exe".to_string(),
+            php_root_dir: "web".to_string(),
+            php_timeout: 30,
+            php_mode: PhpMode::Spawn,
+            php_fastcgi_address: "127.0.0.1:9000".to_string(),
+            php_fastcgi_pool_size: 16,
+            php_pool_min: 2,
+            php_pool_max: 8,
+            php_pool_max_requests: 500,
+            php_pool_queue_timeout: 10,
+            php_front_controller: None,
+            file_extension_cache_times,
+            error_pages,
+            php_env: HashMap::new(),
+            trusted_proxies: Vec::new(),
+            tls: TlsConfig::default(),
+            vhosts: Vec::new(),
+            proxies: Vec::new(),
+            upstreams: Vec::new(),
+            rewrites: Vec::new(),
+            locations: Vec::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("chyba pri cteni konfigurace {}: {}", path, e))?;
+
+        toml::from_str(&content).map_err(|e| format!("chyba pri parsovani konfigurace: {}", e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| format!("chyba pri serializaci konfigurace: {}", e))?;
+
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).map_err(|e| format!("chyba pri vytvareni adresare: {}", e))?;
+            }
+        }
+
+        fs::write(path, content).map_err(|e| format!("chyba pri zapisu konfigurace: {}", e))
+    }
+
+    // doba cachovani pro danou priponu (v sekundach)
+    pub fn get_cache_duration(&self, ext: &str) -> u32 {
+        if !self.cache_enabled {
+            return 0;
+        }
+
+        self.file_extension_cache_times
+            .get(&ext.to_lowercase())
+            .copied()
+            .unwrap_or(self.default_max_age)
+    }
+
+    /// najde vhost pro danou Host hlavicku: presna shoda, potom wildcard,
+    /// potom vhost oznaceny jako `default`
+    pub fn find_vhost(&self, host: &str) -> Option<&VirtualHost> {
+        let host = strip_port(host).trim_end_matches('.').to_ascii_lowercase();
+
+        let exact = self.vhosts
+            .iter()
+            .find(|v| v.server_names.iter().any(|n| n.eq_ignore_ascii_case(&host)));
+        if exact.is_some() {
+            return exact;
+        }
+
+        // wildcard pokryva jen jeden label, takze hostu muze odpovidat nejvys jedna pripona
+        let wildcard = self.vhosts
+            .iter()
+            .find(|v| v.server_names.iter().any(|n| wildcard_matches(n, &host)));
+        if wildcard.is_some() {
+            return wildcard;
+        }
+
+        self.vhosts.iter().find(|v| v.default)
+    }
+
+    /// konfigurace s aplikovanymi prepisy vhostu pro danou Host hlavicku
+    pub fn for_host(&self, host: &str) -> ServerConfig {
+        let mut config = self.clone();
+
+        if let Some(vhost) = self.find_vhost(host) {
+            vhost.apply(&mut config);
+        }
+
+        config
+    }
+
+    /// proxy pravidlo s nejdelsim prefixem odpovidajicim ceste
+    pub fn find_proxy(&self, path: &str) -> Option<&ProxyRule> {
+        self.proxies
+            .iter()
+            .filter(|rule| prefix_matches(&rule.prefix, path))
+            .max_by_key(|rule| rule.prefix.len())
+    }
+
+    /// `spa_fallback` dokumenty (hlavni konfigurace, vhosty, static location), ktere
+    /// ve svem static_root neexistuji - hlasi se pri nacteni, ne u kazdeho requestu
+    pub fn missing_spa_documents(&self) -> Vec<String> {
+        let mut configs = vec![self.clone()];
+        for vhost in &self.vhosts {
+            let mut config = self.clone();
+            vhost.apply(&mut config);
+            configs.push(config);
+        }
+
+        let mut missing = Vec::new();
+        for config in &configs {
+            let mut variants = vec![(config.static_root.clone(), config.spa_fallback.clone())];
+            let static_locations = config.locations
+                .iter()
+                .filter(|l| l.handler == LocationHandler::Static);
+            for location in static_locations {
+                let mut config = config.clone();
+                location.apply(&mut config);
+                variants.push((config.static_root, config.spa_fallback));
+            }
+
+            for (static_root, document) in variants {
+                let document = match document {
+                    Some(document) => document,
+                    None => {
+                        continue;
+                    }
+                };
+
+                let path = Path::new(&static_root).join(document.trim_start_matches('/'));
+                let message = format!("spa_fallback {} neni soubor ve {}", document, static_root);
+                if !path.is_file() && !missing.contains(&message) {
+                    missing.push(message);
+                }
+            }
+        }
+
+        missing
+    }
+
+    // soubory mimo config, jejichz zmena ma vyvolat reload (napr. certifikaty)
+    pub fn watched_files(&self) -> Vec<String> {
+        let mut files = Vec::new();
+
+        if self.tls.enabled {
+            files.push(self.tls.cert_path.clone());
+            files.push(self.tls.key_path.clone());
+
+            for cert in &self.tls.certificates {
+                files.push(cert.cert_path.clone());
+                files.push(cert.key_path.clone());
+            }
+        }
+
+        files
+    }
+}
+
+pub struct ConfigManager {
+    config: Arc<RwLock<ServerConfig>>,
+    config_path: String,
+    listeners: Arc<RwLock<Vec<ReloadListener>>>,
+}
+
+impl ConfigManager {
+    pub fn new(config_path: &str) -> Result<Self, String> {
+        // pokud config neexistuje, vytvorime vychozi
+        if !Path::new(config_path).exists() {
+            ServerConfig::default().save(config_path)?;
+        }
+
+        let config = ServerConfig::load(config_path)?;
+        warn_missing_documents(&config);
+        let config = Arc::new(RwLock::new(config));
+
+        let manager = ConfigManager {
+            config,
+            config_path: config_path.to_string(),
+            listeners: Arc::new(RwLock::new(Vec::new())),
+        };
+
+        manager.start_watcher()?;
+
+        Ok(manager)
+    }
+
+    pub fn get_config(&self) -> ServerConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// konfigurace pro konkretni request (vybrany vhost podle Host hlavicky
+    /// a nastaveni location, kterou request prosel)
+    pub fn get_request_config(&self, req: &HttpRequest) -> ServerConfig {
+        let mut config = self.config.read().unwrap().for_host(req.connection_info().host());
+
+        if let Some(matched) = req.extensions().get::<MatchedLocation>() {
+            matched.location.apply(&mut config);
+        }
+
+        config
+    }
+
+    pub fn get_config_path(&self) -> String {
+        self.config_path.clone()
+    }
+
+    pub fn reload(&self) -> Result<(), String> {
+        reload_into(&self.config_path, &self.config, &self.listeners)
+    }
+
+    /// zaregistruje callback, ktery dostane novou konfiguraci po kazdem reloadu
+    pub fn on_reload<F>(&self, listener: F) where F: Fn(&ServerConfig) + Send + Sync + 'static {
+        self.listeners.write().unwrap().push(Box::new(listener));
+    }
+
+    // sledujeme zmeny konfiguracniho souboru (a souboru na ktere odkazuje)
+    // a automaticky ho znovu nacitame
+    fn start_watcher(&self) -> Result<(), String> {
+        let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+
+        let mut watcher = notify::recommended_watcher(tx)
+            .map_err(|e| format!("chyba pri vytvareni watcheru: {}", e))?;
+
+        let mut watched = WatchedFiles::default();
+        watched.sync(&mut watcher, &self.config_path, &self.get_config());
+
+        let config = self.config.clone();
+        let config_path = self.config_path.clone();
+        let listeners = self.listeners.clone();
+
+        // eventy zpracovavame ve vlastnim vlakne, aby slo z nej menit sledovane adresare
+        std::thread::Builder::new()
+            .name("config-watcher".to_string())
+            .spawn(move || {
+                for res in rx {
+                    let event = match res {
+                        Ok(event) => event,
+                        Err(e) => {
+                            println!("chyba watcheru konfigurace: {}", e);
+                            continue;
+                        }
+                    };
+
+                    if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                        continue;
+                    }
+
+                    if !event.paths.iter().any(|p| watched.contains(p)) {
+                        continue;
+                    }
+
+                    match reload_into(&config_path, &config, &listeners) {
+                        Ok(_) => {
+                            println!("konfigurace byla znovu nactena");
+                            let current = config.read().unwrap().clone();
+                            watched.sync(&mut watcher, &config_path, &current);
+                        }
+                        Err(e) => println!("chyba pri nacteni konfigurace: {}", e),
+                    }
+                }
+            })
+            .map_err(|e| format!("chyba pri spusteni watcheru: {}", e))?;
+
+        Ok(())
+    }
+}
+
+// sledujeme rodicovske adresare, protoze editory a certbot soubory casto nahrazuji
+// prejmenovanim a watch na samotny soubor by se tim ztratil
+#[derive(Default)]
+struct WatchedFiles {
+    dirs: HashSet<PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
+impl WatchedFiles {
+    fn sync(&mut self, watcher: &mut RecommendedWatcher, config_path: &str, config: &ServerConfig) {
+        self.files.clear();
+
+        let mut paths = vec![config_path.to_string()];
+        paths.extend(config.watched_files());
+
+        for path in paths {
+            let path = Path::new(&path);
+
+            let file_name = match path.file_name() {
+                Some(name) => name,
+                None => {
+                    continue;
+                }
+            };
+
+            let parent = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+
+            let dir = match parent.canonicalize() {
+                Ok(dir) => dir,
+                Err(e) => {
+                    println!("nelze sledovat {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            if !self.dirs.contains(&dir) {
+                if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                    println!("nelze sledovat {}: {}", dir.display(), e);
+                    continue;
+                }
+                self.dirs.insert(dir.clone());
+            }
+
+            self.files.insert(dir.join(file_name));
+        }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.files.contains(path)
+    }
+}
+
+fn warn_missing_documents(config: &ServerConfig) {
+    for problem in config.missing_spa_documents() {
+        println!("{}", problem);
+    }
+}
+
+fn reload_into(
+    config_path: &str,
+    config: &RwLock<ServerConfig>,
+    listeners: &RwLock<Vec<ReloadListener>>
+) -> Result<(), String> {
+    let new_config = ServerConfig::load(config_path)?;
+    warn_missing_documents(&new_config);
+    *config.write().unwrap() = new_config.clone();
+
+    for listener in listeners.read().unwrap().iter() {
+        listener(&new_config);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_spa_documents_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::create_dir_all(dir.path().join("app")).unwrap();
+        fs::write(dir.path().join("app/index.html"), "").unwrap();
+
+        let config = ServerConfig {
+            static_root: root("app"),
+            spa_fallback: Some("index.html".to_string()),
+            locations: vec![Location {
+                path: "/admin/".to_string(),
+                root: Some(root("admin")),
+                ..Location::default()
+            }],
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            config.missing_spa_documents(),
+            [format!("spa_fallback index.html neni soubor ve {}", root("admin"))]
+        );
+
+        fs::create_dir_all(dir.path().join("admin")).unwrap();
+        fs::write(dir.path().join("admin/index.html"), "").unwrap();
+        assert!(config.missing_spa_documents().is_empty());
+        assert!(ServerConfig::default().missing_spa_documents().is_empty());
+    }
+
+    fn vhost(names: &[&str], default: bool) -> VirtualHost {
+        VirtualHost {
+            server_names: names.iter().map(|n| n.to_string()).collect(),
+            default,
+            ..VirtualHost::default()
+        }
+    }
+
+    #[test]
+    fn find_vhost_wildcard_covers_one_label() {
+        let config = ServerConfig {
+            vhosts: vec![
+                vhost(&["example.com"], true),
+                vhost(&["*.example.com"], false),
+                vhost(&["*.b.example.com"], false)
+            ],
+            ..ServerConfig::default()
+        };
+        let names = |host: &str| config.find_vhost(host).map(|v| v.server_names[0].clone());
+
+        assert_eq!(names("Example.com:8080").as_deref(), Some("example.com"));
+        assert_eq!(names("a.example.com").as_deref(), Some("*.example.com"));
+        assert_eq!(names("a.b.example.com").as_deref(), Some("*.b.example.com"));
+        // zadny wildcard nepokryva dva labely, zbyva vychozi vhost
+        assert_eq!(names("x.a.c.example.com").as_deref(), Some("example.com"));
+    }
+}
@@ -0,0 +1,294 @@
+use actix_web::HttpRequest;
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use crate::config::{ ServerConfig, SymlinkPolicy };
+
+/// duvod, proc se soubor z korene neposle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccessDenied {
+    /// cesta odpovida vzoru z `deny_patterns`
+    Pattern(String),
+    /// symlink, ktery `follow_symlinks` nepovoluje
+    Symlink(PathBuf),
+    /// kanonicka cesta lezi mimo koren
+    OutsideRoot(PathBuf),
+}
+
+impl fmt::Display for AccessDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessDenied::Pattern(pattern) => write!(f, "zakazano vzorem {}", pattern),
+            AccessDenied::Symlink(link) => write!(f, "nepovoleny symlink {}", link.display()),
+            AccessDenied::OutsideRoot(path) => write!(f, "cesta mimo koren {}", path.display()),
+        }
+    }
+}
+
+/// zkontroluje cestu (uz bez `..`) v koreni podle `deny_patterns` a `follow_symlinks`;
+/// neexistujici soubor projde, 404 vrati az handler
+pub fn check_access(
+    root: &Path,
+    relative_path: &str,
+    config: &ServerConfig
+) -> Result<(), AccessDenied> {
+    if let Some(pattern) = denied_by(relative_path, &config.deny_patterns) {
+        return Err(AccessDenied::Pattern(pattern.to_string()));
+    }
+
+    let root = match fs::canonicalize(root) {
+        Ok(root) => root,
+        Err(_) => {
+            return Ok(());
+        }
+    };
+
+    let mut path = root.clone();
+    let mut followed_symlink = false;
+
+    for segment in relative_path.split('/').filter(|s| !s.is_empty() && *s != ".") {
+        path.push(segment);
+
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                return Ok(());
+            }
+        };
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+
+        match config.follow_symlinks {
+            SymlinkPolicy::On => {}
+            SymlinkPolicy::Off => {
+                return Err(AccessDenied::Symlink(path));
+            }
+            SymlinkPolicy::OwnerMatch => {
+                if !same_owner(&path, &metadata) {
+                    return Err(AccessDenied::Symlink(path));
+                }
+            }
+        }
+        followed_symlink = true;
+    }
+
+    // bez povoleneho symlinku musi kanonicka cesta zustat v koreni
+    if !followed_symlink {
+        if let Ok(canonical) = fs::canonicalize(&path) {
+            if !canonical.starts_with(&root) {
+                return Err(AccessDenied::OutsideRoot(canonical));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// vlastnik odkazu a jeho cile; nefunkcni odkaz neprojde
+#[cfg(unix)]
+fn same_owner(link: &Path, link_metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match fs::metadata(link) {
+        Ok(target) => target.uid() == link_metadata.uid(),
+        Err(_) => false,
+    }
+}
+
+// bez vlastniku souboru se owner_match chova jako off
+#[cfg(not(unix))]
+fn same_owner(_link: &Path, _link_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// prvni vzor, kteremu cesta odpovida; vzor s `/` se porovnava s celou cestou,
+/// ostatni s kazdou jeji casti (`.*` tak zakaze i `.git/config`). Porovnava se
+/// bez ohledu na velikost pismen a bez tecek a mezer na konci casti, jak cesty
+/// chape Windows (`/Backup.BAK.` je tam `backup.bak`)
+pub fn denied_by<'a>(relative_path: &str, patterns: &'a [String]) -> Option<&'a str> {
+    let segments: Vec<&str> = relative_path
+        .split('/')
+        .map(|segment| segment.trim_end_matches(['.', ' ']))
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    let relative_path = segments.join("/");
+
+    patterns
+        .iter()
+        .map(String::as_str)
+        .find(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.trim_matches('/'), &relative_path)
+            } else {
+                segments.iter().any(|segment| glob_match(pattern, segment))
+            }
+        })
+}
+
+/// zapise zamitnuty pristup jako bezpecnostni udalost
+pub fn log_violation(req: &HttpRequest, path: &Path, denied: &impl fmt::Display) {
+    let connection = req.connection_info();
+    println!(
+        "bezpecnost: zamitnut pristup k {} ({}) z {} pro {}",
+        path.display(),
+        denied,
+        connection.realip_remote_addr().unwrap_or("-"),
+        req.path()
+    );
+}
+
+// `*` libovolny pocet znaku, `?` jeden znak; velikost pismen se nerozlisuje
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // posledni `*` a pozice v textu, od ktere se zkousi znovu
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn denied(path: &str) -> Option<String> {
+        let patterns = patterns(&[".*", "*.bak", "*.php", "uploads/*/private"]);
+        denied_by(path, &patterns).map(|pattern| pattern.to_string())
+    }
+
+    #[test]
+    fn hidden_files_and_directories() {
+        assert_eq!(denied(".env").as_deref(), Some(".*"));
+        assert_eq!(denied("app/.git/config").as_deref(), Some(".*"));
+        assert_eq!(denied("/.htaccess").as_deref(), Some(".*"));
+        assert_eq!(denied("app/env"), None);
+    }
+
+    #[test]
+    fn extension_patterns() {
+        assert_eq!(denied("wp-config.php.bak").as_deref(), Some("*.bak"));
+        assert_eq!(denied("admin/index.php").as_deref(), Some("*.php"));
+        assert_eq!(denied("index.phps"), None);
+        assert_eq!(denied("backup.bak.txt"), None);
+    }
+
+    #[test]
+    fn path_patterns_match_whole_path() {
+        assert_eq!(denied("uploads/2024/private").as_deref(), Some("uploads/*/private"));
+        assert_eq!(denied("uploads/2024/private/").as_deref(), Some("uploads/*/private"));
+        assert_eq!(denied("uploads/2024/public"), None);
+        // vzor s `/` neodpovida jen casti cesty
+        assert_eq!(denied("site/uploads/2024/private"), None);
+    }
+
+    #[test]
+    fn case_and_trailing_dots_are_ignored() {
+        assert_eq!(denied(".ENV").as_deref(), Some(".*"));
+        assert_eq!(denied("Backup.BAK").as_deref(), Some("*.bak"));
+        assert_eq!(denied("secret.bak.").as_deref(), Some("*.bak"));
+        assert_eq!(denied("secret.bak. . ").as_deref(), Some("*.bak"));
+        assert_eq!(denied("INDEX.PHP").as_deref(), Some("*.php"));
+        assert_eq!(denied("Uploads/2024/PRIVATE.").as_deref(), Some("uploads/*/private"));
+        assert!(denied_by("a.txt", &patterns(&["A.TXT"])).is_some());
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("*.b?k", "x.bak"));
+        assert!(glob_match("a*b*c", "a-b-b-c"));
+        assert!(!glob_match("a*b*c", "a-b-b-d"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn check_access_uses_deny_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ServerConfig::default();
+
+        assert_eq!(
+            check_access(dir.path(), "config.BAK", &config),
+            Err(AccessDenied::Pattern("*.bak".to_string()))
+        );
+        // neexistujici soubor projde, 404 vrati handler
+        assert_eq!(check_access(dir.path(), "missing.txt", &config), Ok(()));
+    }
+
+    // koren web/ s odkazem dovnitr (inner -> web/data) a ven (outer -> mimo koren)
+    #[cfg(unix)]
+    fn linked_root() -> tempfile::TempDir {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("web");
+        fs::create_dir_all(root.join("data")).unwrap();
+        fs::write(root.join("data/a.txt"), "a").unwrap();
+        fs::create_dir_all(dir.path().join("outside")).unwrap();
+        fs::write(dir.path().join("outside/b.txt"), "b").unwrap();
+        symlink(root.join("data"), root.join("inner")).unwrap();
+        symlink(dir.path().join("outside"), root.join("outer")).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_symlinks_modes() {
+        let dir = linked_root();
+        let root = dir.path().join("web");
+        let access = |policy: SymlinkPolicy, path: &str| {
+            let config = ServerConfig { follow_symlinks: policy, ..ServerConfig::default() };
+            check_access(&root, path, &config)
+        };
+
+        assert_eq!(access(SymlinkPolicy::Off, "data/a.txt"), Ok(()));
+        assert_eq!(
+            access(SymlinkPolicy::Off, "inner/a.txt"),
+            Err(AccessDenied::Symlink(fs::canonicalize(&root).unwrap().join("inner")))
+        );
+        assert!(access(SymlinkPolicy::Off, "outer/b.txt").is_err());
+
+        // odkaz a cil patri stejnemu uzivateli
+        assert_eq!(access(SymlinkPolicy::OwnerMatch, "inner/a.txt"), Ok(()));
+        assert_eq!(access(SymlinkPolicy::OwnerMatch, "outer/b.txt"), Ok(()));
+
+        assert_eq!(access(SymlinkPolicy::On, "inner/a.txt"), Ok(()));
+        assert_eq!(access(SymlinkPolicy::On, "outer/b.txt"), Ok(()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlink_is_denied_with_owner_match() {
+        let dir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(dir.path().join("missing"), dir.path().join("broken"))
+            .unwrap();
+        let config = ServerConfig::default();
+
+        assert!(matches!(
+            check_access(dir.path(), "broken", &config),
+            Err(AccessDenied::Symlink(_))
+        ));
+    }
+}
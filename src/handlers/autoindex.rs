@@ -0,0 +1,312 @@
+use actix_web::http::header;
+use actix_web::{ web, HttpRequest, HttpResponse };
+use chrono::{ DateTime, Utc };
+use percent_encoding::{ percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC };
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::config::ServerConfig;
+use crate::handlers::access::check_access;
+
+// znaky, ktere muzou zustat v segmentu URL bez kodovani
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+#[derive(Serialize)]
+struct Listing {
+    path: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// u adresaru 0
+    size: u64,
+    /// RFC 3339, UTC
+    modified: Option<String>,
+    #[serde(skip)]
+    modified_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+    Name,
+    Size,
+    Modified,
+}
+
+/// vypis adresare - HTML tabulka razena podle `?sort=name|size|modified&order=asc|desc`,
+/// s `Accept: application/json` JSON; skryte, zakazane a nepovolene symlinky
+/// se nevypisuji
+pub fn directory_listing(
+    req: &HttpRequest,
+    config: &ServerConfig,
+    root: &Path,
+    relative_path: &str
+) -> HttpResponse {
+    let dir = root.join(relative_path);
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            println!("chyba pri cteni adresare {}: {}", dir.display(), e);
+            return HttpResponse::InternalServerError().body("chyba pri cteni adresare");
+        }
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir.flatten() {
+        // nazvy mimo UTF-8 by stejne nesly otevrit pres URL
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => {
+                continue;
+            }
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let entry_path = format!("{}/{}", relative_path.trim_end_matches('/'), name);
+        if check_access(root, &entry_path, config).is_err() {
+            continue;
+        }
+
+        // metadata cile odkazu; nefunkcni symlinky se preskoci
+        let metadata = match fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                continue;
+            }
+        };
+
+        let modified_time = metadata.modified().ok().map(DateTime::<Utc>::from);
+        entries.push(Entry {
+            name,
+            kind: if metadata.is_dir() { "dir" } else { "file" },
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: modified_time.map(|time| time.to_rfc3339()),
+            modified_time,
+        });
+    }
+
+    let query = web::Query::<HashMap<String, String>>
+        ::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    let sort_by = match query.get("sort").map(String::as_str) {
+        Some("size") => SortBy::Size,
+        Some("modified") => SortBy::Modified,
+        _ => SortBy::Name,
+    };
+    let descending = query.get("order").map(String::as_str) == Some("desc");
+
+    // adresare vzdy nahore
+    entries.sort_by(|a, b| {
+        let order = match sort_by {
+            SortBy::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortBy::Size => a.size.cmp(&b.size),
+            SortBy::Modified => a.modified_time.cmp(&b.modified_time),
+        };
+        let order = if descending { order.reverse() } else { order };
+        (b.kind == "dir").cmp(&(a.kind == "dir")).then(order)
+    });
+
+    // odkazy jsou absolutni, funguji i bez lomitka na konci URL
+    let base = if req.path().ends_with('/') {
+        req.path().to_string()
+    } else {
+        format!("{}/", req.path())
+    };
+    let display_path = percent_decode_str(&base).decode_utf8_lossy().into_owned();
+
+    let wants_json = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.contains("application/json"))
+        .unwrap_or(false);
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .insert_header((header::VARY, "Accept"));
+
+    if wants_json {
+        let listing = Listing { path: display_path, entries };
+        return match serde_json::to_string_pretty(&listing) {
+            Ok(json) => response.content_type("application/json").body(json),
+            Err(_) => {
+                HttpResponse::InternalServerError().body("chyba pri serializaci vypisu adresare")
+            }
+        };
+    }
+
+    response
+        .content_type("text/html; charset=utf-8")
+        .body(render_html(&base, &display_path, &entries, sort_by, descending))
+}
+
+fn render_html(
+    base: &str,
+    display_path: &str,
+    entries: &[Entry],
+    sort_by: SortBy,
+    descending: bool
+) -> String {
+    let title = escape_html(display_path);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Index of {0}</title>\n\
+         <style>body{{font-family:sans-serif}}td,th{{padding:2px 12px;text-align:left}}\
+         td.size{{text-align:right}}</style>\n\
+         </head>\n<body>\n<h1>Index of {0}</h1>\n<table>\n<tr>",
+        title
+    );
+
+    // klik na aktualni sloupec obrati poradi
+    let columns = [(SortBy::Name, "name"), (SortBy::Size, "size"), (SortBy::Modified, "modified")];
+    for (column, label) in columns {
+        let order = if column == sort_by && !descending { "desc" } else { "asc" };
+        html.push_str(
+            &format!("<th><a href=\"?sort={0}&amp;order={1}\">{0}</a></th>", label, order)
+        );
+    }
+    html.push_str("</tr>\n");
+
+    if base != "/" {
+        let parent = match base.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, _)) => format!("{}/", parent),
+            None => "/".to_string(),
+        };
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">../</a></td><td></td><td></td></tr>\n",
+            escape_html(&parent)
+        ));
+    }
+
+    for entry in entries {
+        let is_dir = entry.kind == "dir";
+        let href = format!(
+            "{}{}{}",
+            base,
+            utf8_percent_encode(&entry.name, SEGMENT),
+            if is_dir { "/" } else { "" }
+        );
+        let size = if is_dir { "-".to_string() } else { format_size(entry.size) };
+        let modified = entry.modified_time
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+            escape_html(&href),
+            escape_html(&entry.name),
+            if is_dir { "/" } else { "" },
+            size,
+            modified
+        ));
+    }
+
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::test::TestRequest;
+
+    // adresar s beznymi, skrytymi a zakazanymi soubory
+    fn listed_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("files/docs")).unwrap();
+        fs::create_dir_all(root.join("files/.git")).unwrap();
+        fs::create_dir_all(root.join("files/private")).unwrap();
+        for file in ["readme.txt", ".env", "config.bak", "index.php", "<b>.txt"] {
+            fs::write(root.join("files").join(file), "obsah").unwrap();
+        }
+        dir
+    }
+
+    fn config() -> ServerConfig {
+        let mut config = ServerConfig::default();
+        config.deny_patterns.push("files/private".to_string());
+        config
+    }
+
+    async fn listing(root: &Path, accept: &str) -> String {
+        let req = TestRequest::get()
+            .uri("/files")
+            .insert_header((header::ACCEPT, accept))
+            .to_http_request();
+        let response = directory_listing(&req, &config(), root, "files");
+        assert_eq!(response.status(), 200);
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn html_listing_hides_denied_entries() {
+        let dir = listed_dir();
+        let html = listing(dir.path(), "text/html").await;
+
+        assert!(html.contains("href=\"/files/readme.txt\""));
+        assert!(html.contains("href=\"/files/docs/\""));
+        // nazvy se escapuji i v odkazu
+        assert!(html.contains("&lt;b&gt;.txt"));
+        assert!(!html.contains("<b>.txt"));
+        for hidden in [".env", ".git", "config.bak", "index.php", "private"] {
+            assert!(!html.contains(hidden), "{} je ve vypisu", hidden);
+        }
+    }
+
+    #[actix_web::test]
+    async fn json_listing_hides_denied_entries() {
+        let dir = listed_dir();
+        let json = listing(dir.path(), "application/json").await;
+        let listing: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(listing["path"], "/files/");
+        let names: Vec<&str> = listing["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap())
+            .collect();
+        // adresare nahore, potom podle nazvu
+        assert_eq!(names, ["docs", "<b>.txt", "readme.txt"]);
+        assert_eq!(listing["entries"][0]["type"], "dir");
+        assert_eq!(listing["entries"][2]["size"], 5);
+    }
+}
@@ -0,0 +1,644 @@
+use actix_web::http::Version;
+use actix_web::{ HttpMessage, HttpRequest };
+use std::collections::HashMap;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{ Path, PathBuf };
+use crate::config::ServerConfig;
+use crate::middleware::rewrite::OriginalUri;
+use crate::utils::strip_port;
+
+const SERVER_SOFTWARE: &str = concat!("Qlira/", env!("CARGO_PKG_VERSION"));
+
+/// udaje o requestu potrebne pro CGI prostredi; oddelene od actixu, aby slo
+/// prostredi sestavit (a otestovat) bez beziciho serveru
+#[derive(Debug, Clone, Default)]
+pub struct CgiRequestInfo {
+    pub method: String,
+    /// puvodni cesta vcetne query stringu (pred internim prepisem)
+    pub request_uri: String,
+    pub query_string: String,
+    pub protocol: String,
+    pub server_name: String,
+    pub server_addr: Option<SocketAddr>,
+    pub remote_addr: Option<SocketAddr>,
+    pub https: bool,
+    /// hlavicky s jmeny malymi pismeny, opakovane hlavicky uz spojene
+    pub headers: Vec<(String, String)>,
+    pub content_length: usize,
+}
+
+/// PHP script nalezeny v URL a zbytek cesty za nim
+#[derive(Debug, Clone)]
+pub struct ScriptInfo {
+    pub document_root: PathBuf,
+    /// URL scriptu, napr. `/php/index.php`
+    pub script_name: String,
+    /// absolutni cesta k souboru scriptu
+    pub script_filename: PathBuf,
+    /// cast URL za scriptem, napr. `/foo/bar` pro `/php/index.php/foo/bar`
+    pub path_info: String,
+}
+
+impl CgiRequestInfo {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let mut headers: Vec<(String, String)> = Vec::new();
+
+        for (name, value) in req.headers() {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => {
+                    continue;
+                }
+            };
+
+            // opakovane hlavicky spojime do jedne promenne (cookies pres "; ")
+            match headers.iter_mut().find(|(n, _)| n == name.as_str()) {
+                Some((_, existing)) => {
+                    existing.push_str(if name == "cookie" { "; " } else { ", " });
+                    existing.push_str(value);
+                }
+                None => headers.push((name.as_str().to_string(), value.to_string())),
+            }
+        }
+
+        // extensions se nesmi drzet pres connection_info(), ktere si je pujcuje mutabilne
+        let original_uri = req.extensions().get::<OriginalUri>().map(|uri| uri.0.clone());
+        let request_uri = original_uri.unwrap_or_else(|| {
+            req.uri()
+                .path_and_query()
+                .map(|pq| pq.as_str().to_string())
+                .unwrap_or_else(|| req.path().to_string())
+        });
+
+        CgiRequestInfo {
+            method: req.method().to_string(),
+            request_uri,
+            query_string: req.query_string().to_string(),
+            protocol: protocol_name(req.version()).to_string(),
+            server_name: strip_port(req.connection_info().host()).to_string(),
+            server_addr: Some(req.app_config().local_addr()),
+            remote_addr: req.peer_addr(),
+            https: req.app_config().secure(),
+            headers,
+            content_length: 0,
+        }
+    }
+}
+
+fn protocol_name(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+/// najde script v `document_root` podle cesty za `url_prefix` - prvni segment,
+/// ktery je soubor, je script a zbytek je PATH_INFO; adresar bez dalsich
+/// segmentu pouzije prvni existujici soubor z `index_files`; soubor, ktery neni
+/// `.php`, script neni (jinak by `/uploads/avatar.jpg/x.php` spustil nahrany obrazek)
+pub fn resolve_script(
+    document_root: &Path,
+    url_prefix: &str,
+    relative_path: &str,
+    index_files: &[String]
+) -> Option<ScriptInfo> {
+    let document_root = fs::canonicalize(document_root).ok()?;
+    let url_prefix = url_prefix.trim_end_matches('/');
+
+    let segments: Vec<&str> = relative_path.trim_start_matches('/').split('/').collect();
+    let mut filename = document_root.clone();
+    let mut script_segments = Vec::new();
+
+    for (index, segment) in segments.iter().enumerate() {
+        if *segment == ".." {
+            return None;
+        }
+        if segment.is_empty() || *segment == "." {
+            continue;
+        }
+
+        filename.push(segment);
+        script_segments.push(*segment);
+
+        if filename.is_file() {
+            if !is_php(&filename) {
+                return None;
+            }
+
+            let rest = &segments[index + 1..];
+            let path_info = if rest.is_empty() { String::new() } else { format!("/{}", rest.join("/")) };
+
+            return Some(ScriptInfo {
+                script_name: format!("{}/{}", url_prefix, script_segments.join("/")),
+                script_filename: filename,
+                path_info,
+                document_root,
+            });
+        }
+
+        if !filename.is_dir() {
+            return None;
+        }
+    }
+
+    // cesta konci v adresari
+    let index = directory_index(&filename, index_files)?;
+    if !is_php(Path::new(index)) {
+        return None;
+    }
+    filename.push(index);
+    script_segments.push(index);
+
+    Some(ScriptInfo {
+        script_name: format!("{}/{}", url_prefix, script_segments.join("/")),
+        script_filename: filename,
+        path_info: String::new(),
+        document_root,
+    })
+}
+
+/// prvni soubor z `index_files`, ktery v adresari existuje
+pub fn directory_index<'a>(dir: &Path, index_files: &'a [String]) -> Option<&'a str> {
+    index_files
+        .iter()
+        .map(|name| name.trim_matches('/'))
+        .find(|name| !name.is_empty() && dir.join(name).is_file())
+}
+
+/// cil requestu v rezimu front controlleru
+#[derive(Debug, Clone)]
+pub enum PhpTarget {
+    Script(ScriptInfo),
+    StaticFile(PathBuf),
+}
+
+/// cil PHP requestu bez front controlleru - soubor nebo index adresare, ktery
+/// neni PHP (`index.html`, obrazek), se posle staticky, jinak se spusti script
+pub fn resolve_target(
+    document_root: &Path,
+    url_prefix: &str,
+    relative_path: &str,
+    index_files: &[String]
+) -> Option<PhpTarget> {
+    let root = fs::canonicalize(document_root).ok()?;
+    let relative = relative_path.trim_start_matches('/');
+    if relative.split('/').any(|s| s == "..") {
+        return None;
+    }
+
+    let requested = root.join(relative);
+    if requested.is_file() && !is_php(&requested) {
+        return Some(PhpTarget::StaticFile(requested));
+    }
+
+    if requested.is_dir() {
+        if let Some(index) = directory_index(&requested, index_files) {
+            if !is_php(Path::new(index)) {
+                return Some(PhpTarget::StaticFile(requested.join(index)));
+            }
+        }
+    }
+
+    resolve_script(&root, url_prefix, relative, index_files).map(PhpTarget::Script)
+}
+
+/// `try_files` pro PHP frameworky: existujici soubor (PHP se spusti, ostatni se
+/// posle staticky), index adresare, script s PATH_INFO a nakonec `front_controller`,
+/// ktery dostane celou puvodni cestu jako PATH_INFO
+pub fn try_files(
+    document_root: &Path,
+    url_prefix: &str,
+    relative_path: &str,
+    front_controller: &str,
+    index_files: &[String]
+) -> Option<PhpTarget> {
+    let root = fs::canonicalize(document_root).ok()?;
+    let relative = relative_path.trim_start_matches('/');
+
+    let segments: Vec<&str> = relative
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .collect();
+    if segments.contains(&"..") {
+        return None;
+    }
+
+    // skryte soubory (.env, .git) staticky neposilame, dostane je front controller
+    if !segments.iter().any(|s| s.starts_with('.')) {
+        let candidate = segments.iter().fold(root.clone(), |path, s| path.join(s));
+
+        if candidate.is_file() && !is_php(&candidate) {
+            return Some(PhpTarget::StaticFile(candidate));
+        }
+
+        if candidate.is_dir() {
+            if let Some(index) = directory_index(&candidate, index_files) {
+                let index = candidate.join(index);
+                if !is_php(&index) {
+                    return Some(PhpTarget::StaticFile(index));
+                }
+            }
+        }
+
+        // PHP soubor, PHP index adresare nebo /script.php/path/info; `/avatar.jpg/x.php`
+        // script nema (resolve_script bere jen .php) a dostane ho front controller
+        if let Some(script) = resolve_script(&root, url_prefix, relative, index_files) {
+            return Some(PhpTarget::Script(script));
+        }
+    }
+
+    let front_controller = front_controller.trim_start_matches('/');
+    let script_filename = root.join(front_controller);
+    if !script_filename.is_file() {
+        return None;
+    }
+
+    Some(
+        PhpTarget::Script(ScriptInfo {
+            script_name: format!("{}/{}", url_prefix.trim_end_matches('/'), front_controller),
+            script_filename,
+            path_info: format!("/{}", relative),
+            document_root: root,
+        })
+    )
+}
+
+pub fn is_php(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("php"))
+}
+
+/// sestavi CGI/1.1 promenne prostredi (RFC 3875) pro PHP-CGI i FastCGI;
+/// `php_env` z konfigurace se aplikuje jako posledni
+pub fn build_env(
+    request: &CgiRequestInfo,
+    script: &ScriptInfo,
+    config: &ServerConfig
+) -> HashMap<String, String> {
+    let mut env = HashMap::new();
+    let mut set = |name: &str, value: String| {
+        env.insert(name.to_string(), value);
+    };
+
+    set("GATEWAY_INTERFACE", "CGI/1.1".to_string());
+    set("SERVER_SOFTWARE", SERVER_SOFTWARE.to_string());
+    set("SERVER_PROTOCOL", request.protocol.clone());
+    set("SERVER_NAME", request.server_name.clone());
+    set("REQUEST_METHOD", request.method.clone());
+    set("REQUEST_URI", request.request_uri.clone());
+    set("QUERY_STRING", request.query_string.clone());
+    set("REQUEST_SCHEME", (if request.https { "https" } else { "http" }).to_string());
+
+    if let Some(addr) = request.server_addr {
+        set("SERVER_ADDR", addr.ip().to_string());
+        set("SERVER_PORT", addr.port().to_string());
+    }
+    if let Some(addr) = request.remote_addr {
+        set("REMOTE_ADDR", addr.ip().to_string());
+        set("REMOTE_PORT", addr.port().to_string());
+    }
+    if request.https {
+        set("HTTPS", "on".to_string());
+    }
+
+    let document_root = cgi_path(&script.document_root);
+    set("SCRIPT_NAME", script.script_name.clone());
+    set("SCRIPT_FILENAME", cgi_path(&script.script_filename));
+    set("PHP_SELF", format!("{}{}", script.script_name, script.path_info));
+    if !script.path_info.is_empty() {
+        set("PATH_INFO", script.path_info.clone());
+        set("PATH_TRANSLATED", format!("{}{}", document_root, script.path_info));
+    }
+    set("DOCUMENT_ROOT", document_root);
+
+    // php-cgi s cgi.force_redirect odmitne request bez REDIRECT_STATUS
+    set("REDIRECT_STATUS", "200".to_string());
+
+    if request.content_length > 0 {
+        set("CONTENT_LENGTH", request.content_length.to_string());
+    }
+
+    for (name, value) in &request.headers {
+        match name.as_str() {
+            "content-type" => set("CONTENT_TYPE", value.clone()),
+            "content-length" => {}
+            // HTTP_PROXY by PHP knihovny braly jako nastaveni proxy (httpoxy)
+            "proxy" => {}
+            // `X-Foo_Bar` by prepsal `X-Foo-Bar` (obe jsou HTTP_X_FOO_BAR) - zahodime ho jako nginx
+            name if name.contains('_') => {}
+            _ => set(&format!("HTTP_{}", name.replace('-', "_").to_uppercase()), value.clone()),
+        }
+    }
+
+    // pridame environment variables pro debugovani, kdyz je zapnuty development mode
+    if config.development_mode {
+        set("DEVELOPMENT_MODE", "1".to_string());
+    }
+
+    for (name, value) in &config.php_env {
+        set(name, value.clone());
+    }
+
+    env
+}
+
+/// cesta predavana PHP; canonicalize na Windows vraci `\\?\C:\...`, se kterym si PHP neporadi
+pub fn cgi_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    match path.strip_prefix(r"\\?\") {
+        Some(stripped) => stripped.to_string(),
+        None => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn index_files() -> Vec<String> {
+        vec!["index.php".to_string(), "index.html".to_string()]
+    }
+
+    // docroot: index.php, app.js, .env, blog/index.html, admin/index.php,
+    // uploads/avatar.jpg
+    fn document_root() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("blog")).unwrap();
+        fs::create_dir_all(root.join("admin")).unwrap();
+        fs::create_dir_all(root.join("uploads")).unwrap();
+        let files = [
+            "index.php",
+            "app.js",
+            ".env",
+            "blog/index.html",
+            "admin/index.php",
+            "uploads/avatar.jpg",
+        ];
+        for file in files {
+            fs::write(root.join(file), "").unwrap();
+        }
+        dir
+    }
+
+    fn request() -> CgiRequestInfo {
+        CgiRequestInfo {
+            method: "GET".to_string(),
+            request_uri: "/php/index.php/foo/bar?x=1".to_string(),
+            query_string: "x=1".to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            server_name: "example.com".to_string(),
+            server_addr: Some("10.0.0.1:8443".parse().unwrap()),
+            remote_addr: Some("203.0.113.9:51000".parse().unwrap()),
+            ..CgiRequestInfo::default()
+        }
+    }
+
+    fn script(path: &str) -> ScriptInfo {
+        ScriptInfo {
+            document_root: PathBuf::from("/srv/web"),
+            script_name: "/php/index.php".to_string(),
+            script_filename: PathBuf::from("/srv/web/index.php"),
+            path_info: path.to_string(),
+        }
+    }
+
+    fn script_of(target: Option<PhpTarget>) -> ScriptInfo {
+        match target {
+            Some(PhpTarget::Script(script)) => script,
+            other => panic!("ocekavan script, je {:?}", other),
+        }
+    }
+
+    fn static_of(target: Option<PhpTarget>) -> PathBuf {
+        match target {
+            Some(PhpTarget::StaticFile(path)) => path,
+            other => panic!("ocekavan staticky soubor, je {:?}", other),
+        }
+    }
+
+    #[test]
+    fn path_info_is_split_from_script() {
+        let dir = document_root();
+        let root = fs::canonicalize(dir.path()).unwrap();
+
+        let script = resolve_script(dir.path(), "/php/", "index.php/foo/bar", &index_files())
+            .unwrap();
+        assert_eq!(script.script_name, "/php/index.php");
+        assert_eq!(script.script_filename, root.join("index.php"));
+        assert_eq!(script.path_info, "/foo/bar");
+
+        let env = build_env(&request(), &script, &ServerConfig::default());
+        let root = cgi_path(&root);
+        assert_eq!(env["SCRIPT_NAME"], "/php/index.php");
+        assert_eq!(env["PATH_INFO"], "/foo/bar");
+        assert_eq!(env["PATH_TRANSLATED"], format!("{}/foo/bar", root));
+        assert_eq!(env["PHP_SELF"], "/php/index.php/foo/bar");
+        assert_eq!(env["DOCUMENT_ROOT"], root);
+        assert_eq!(env["REQUEST_URI"], "/php/index.php/foo/bar?x=1");
+    }
+
+    #[test]
+    fn path_info_is_omitted_without_suffix() {
+        let env = build_env(&request(), &script(""), &ServerConfig::default());
+
+        assert!(!env.contains_key("PATH_INFO"));
+        assert!(!env.contains_key("PATH_TRANSLATED"));
+        assert_eq!(env["PHP_SELF"], "/php/index.php");
+    }
+
+    #[test]
+    fn server_and_remote_addresses() {
+        let env = build_env(&request(), &script(""), &ServerConfig::default());
+
+        assert_eq!(env["SERVER_ADDR"], "10.0.0.1");
+        assert_eq!(env["SERVER_PORT"], "8443");
+        assert_eq!(env["REMOTE_ADDR"], "203.0.113.9");
+        assert_eq!(env["REMOTE_PORT"], "51000");
+        assert_eq!(env["SERVER_NAME"], "example.com");
+        assert_eq!(env["GATEWAY_INTERFACE"], "CGI/1.1");
+        assert_eq!(env["REDIRECT_STATUS"], "200");
+    }
+
+    #[test]
+    fn https_is_set_behind_tls() {
+        let plain = build_env(&request(), &script(""), &ServerConfig::default());
+        assert!(!plain.contains_key("HTTPS"));
+        assert_eq!(plain["REQUEST_SCHEME"], "http");
+
+        let tls = CgiRequestInfo { https: true, ..request() };
+        let env = build_env(&tls, &script(""), &ServerConfig::default());
+        assert_eq!(env["HTTPS"], "on");
+        assert_eq!(env["REQUEST_SCHEME"], "https");
+    }
+
+    #[test]
+    fn php_env_overrides_defaults() {
+        let mut config = ServerConfig::default();
+        config.php_env.insert("APP_ENV".to_string(), "production".to_string());
+        config.php_env.insert("SERVER_NAME".to_string(), "www.example.com".to_string());
+
+        let env = build_env(&request(), &script(""), &config);
+
+        assert_eq!(env["APP_ENV"], "production");
+        assert_eq!(env["SERVER_NAME"], "www.example.com");
+    }
+
+    #[test]
+    fn request_headers_are_mapped() {
+        let headers = [
+            ("content-type", "application/json"),
+            ("content-length", "12"),
+            ("proxy", "http://evil.example:8080"),
+            ("x-foo-bar", "pomlcky"),
+            ("x-foo_bar", "podtrzitka"),
+            ("accept-language", "cs"),
+        ];
+        let mut request = request();
+        request.content_length = 12;
+
+        // poradi hlavicek nesmi ovlivnit vysledek
+        for reversed in [false, true] {
+            let mut headers: Vec<(String, String)> = headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect();
+            if reversed {
+                headers.reverse();
+            }
+            request.headers = headers;
+
+            let env = build_env(&request, &script(""), &ServerConfig::default());
+
+            assert_eq!(env["CONTENT_TYPE"], "application/json");
+            assert_eq!(env["CONTENT_LENGTH"], "12");
+            assert!(!env.contains_key("HTTP_CONTENT_TYPE"));
+            assert!(!env.contains_key("HTTP_CONTENT_LENGTH"));
+            assert!(!env.contains_key("HTTP_PROXY"));
+            assert_eq!(env["HTTP_X_FOO_BAR"], "pomlcky");
+            assert_eq!(env["HTTP_ACCEPT_LANGUAGE"], "cs");
+        }
+    }
+
+    #[actix_web::test]
+    async fn request_info_joins_repeated_headers() {
+        let req = actix_web::test::TestRequest::post()
+            .uri("/php/index.php?a=1")
+            .insert_header(("host", "example.com:8080"))
+            .append_header(("cookie", "a=1"))
+            .append_header(("cookie", "b=2"))
+            .append_header(("accept", "text/html"))
+            .append_header(("accept", "application/json"))
+            .to_http_request();
+
+        let info = CgiRequestInfo::from_request(&req);
+        let header = |name: &str| {
+            info.headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+        };
+
+        assert_eq!(info.method, "POST");
+        assert_eq!(info.request_uri, "/php/index.php?a=1");
+        assert_eq!(info.query_string, "a=1");
+        assert_eq!(info.server_name, "example.com");
+        assert_eq!(header("cookie"), Some("a=1; b=2"));
+        assert_eq!(header("accept"), Some("text/html, application/json"));
+    }
+
+    #[test]
+    fn resolve_script_uses_directory_index() {
+        let dir = document_root();
+        let root = fs::canonicalize(dir.path()).unwrap();
+
+        let script = resolve_script(dir.path(), "/", "admin/", &index_files()).unwrap();
+        assert_eq!(script.script_name, "/admin/index.php");
+        assert_eq!(script.script_filename, root.join("admin/index.php"));
+        assert_eq!(script.path_info, "");
+
+        assert!(resolve_script(dir.path(), "/", "missing.php", &index_files()).is_none());
+        assert!(resolve_script(dir.path(), "/", "blog/../index.php", &index_files()).is_none());
+        assert!(resolve_script(dir.path(), "/", "index.php", &[]).is_some());
+        assert!(resolve_script(dir.path(), "/", "admin", &[]).is_none());
+    }
+
+    #[test]
+    fn only_php_files_are_scripts() {
+        let dir = document_root();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let resolve = |path: &str| resolve_script(dir.path(), "/", path, &index_files());
+
+        // cgi.fix_pathinfo - nahrany obrazek se nesmi spustit jako PHP
+        assert!(resolve("uploads/avatar.jpg/x.php").is_none());
+        assert!(resolve("uploads/avatar.jpg").is_none());
+        assert!(resolve("blog/").is_none());
+
+        let target = |path: &str| resolve_target(dir.path(), "/", path, &index_files());
+        assert!(target("uploads/avatar.jpg/x.php").is_none());
+        assert_eq!(static_of(target("uploads/avatar.jpg")), root.join("uploads/avatar.jpg"));
+        assert_eq!(static_of(target("blog")), root.join("blog/index.html"));
+        assert_eq!(script_of(target("admin/")).script_name, "/admin/index.php");
+        assert_eq!(script_of(target("index.php/a")).path_info, "/a");
+        assert!(target("blog/../index.php").is_none());
+    }
+
+    #[test]
+    fn try_files_prefers_existing_files() {
+        let dir = document_root();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let try_files = |path: &str| try_files(dir.path(), "/", path, "index.php", &index_files());
+
+        assert_eq!(static_of(try_files("app.js")), root.join("app.js"));
+        assert_eq!(static_of(try_files("blog/")), root.join("blog/index.html"));
+
+        let admin = script_of(try_files("admin"));
+        assert_eq!(admin.script_name, "/admin/index.php");
+        assert_eq!(admin.path_info, "");
+
+        let with_path_info = script_of(try_files("index.php/api/users"));
+        assert_eq!(with_path_info.script_name, "/index.php");
+        assert_eq!(with_path_info.path_info, "/api/users");
+    }
+
+    #[test]
+    fn try_files_falls_back_to_front_controller() {
+        let dir = document_root();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let try_files = |path: &str| try_files(dir.path(), "/", path, "index.php", &index_files());
+
+        let route = script_of(try_files("users/42/edit"));
+        assert_eq!(route.script_name, "/index.php");
+        assert_eq!(route.path_info, "/users/42/edit");
+
+        // skryte soubory se neposilaji, dostane je aplikace
+        assert_eq!(script_of(try_files(".env")).path_info, "/.env");
+
+        // soubor, ktery neni PHP, se s PATH_INFO nespusti
+        for path in ["uploads/avatar.jpg/x.php", "app.js/foo"] {
+            let route = script_of(try_files(path));
+            assert_eq!(route.script_name, "/index.php");
+            assert_eq!(route.script_filename, root.join("index.php"));
+            assert_eq!(route.path_info, format!("/{}", path));
+        }
+
+        assert!(try_files("../etc/passwd").is_none());
+        assert!(
+            super::try_files(dir.path(), "/", "users", "missing.php", &index_files()).is_none()
+        );
+        // bez front controlleru nezbyde nic
+        assert!(
+            super::try_files(dir.path(), "/", "uploads/avatar.jpg/x.php", "missing.php", &[])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn php_extension_is_case_insensitive() {
+        assert!(is_php(Path::new("index.php")));
+        assert!(is_php(Path::new("INDEX.PHP")));
+        assert!(!is_php(Path::new("index.phps")));
+        assert!(!is_php(Path::new("php")));
+    }
+}
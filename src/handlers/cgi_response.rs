@@ -0,0 +1,260 @@
+use actix_web::{ web::Bytes, HttpResponse, HttpResponseBuilder };
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ HeaderName, HeaderValue };
+use futures::channel::mpsc;
+use futures::{ stream, SinkExt, StreamExt };
+use std::future::Future;
+use std::io;
+
+// kdyz vystup nema oddelene hlavicky ani po 64 KB, bereme ho cely jako body
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+const CHANNEL_CAPACITY: usize = 16;
+
+/// kanal, do ktereho zdroj (PHP proces, FastCGI) posila stdout po kouscich
+pub type BodySender = mpsc::Sender<Result<Bytes, io::Error>>;
+type BodyReceiver = mpsc::Receiver<Result<Bytes, io::Error>>;
+
+/// spusti `producer` na pozadi a z jeho stdout sestavi CGI odpoved - jakmile
+/// jsou hlavicky kompletni, body se posila klientovi prubezne
+pub async fn stream_cgi_response<F, Fut>(producer: F) -> HttpResponse
+    where F: FnOnce(BodySender) -> Fut, Fut: Future<Output = Result<(), String>> + 'static
+{
+    let mut output = spawn_producer(producer);
+    let mut buffer = Vec::new();
+
+    loop {
+        if let Some((header_len, separator_len)) = find_header_end(&buffer) {
+            let body = buffer.split_off(header_len + separator_len);
+            buffer.truncate(header_len);
+
+            let mut builder = build_headers(&buffer);
+            return builder.streaming(prepend(body, output));
+        }
+
+        if buffer.len() > MAX_HEADER_SIZE {
+            break;
+        }
+
+        match output.next().await {
+            Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+            Some(Err(e)) => {
+                return HttpResponse::InternalServerError().body(format!("php error: {}", e));
+            }
+            None => {
+                break;
+            }
+        }
+    }
+
+    // kdyz neni header a body oddeleny
+    HttpResponse::Ok().content_type("text/html").streaming(prepend(buffer, output))
+}
+
+/// jako `stream_cgi_response`, ale cely vystup je body (napr. PHP CLI);
+/// chyba pred prvnim vystupem se vraci jako `Err`
+pub async fn stream_body<F, Fut>(content_type: &str, producer: F) -> Result<HttpResponse, String>
+    where F: FnOnce(BodySender) -> Fut, Fut: Future<Output = Result<(), String>> + 'static
+{
+    let mut output = spawn_producer(producer);
+
+    // pockame na prvni kus, abychom jeste mohli vratit chybu misto 200
+    let first = match output.next().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => {
+            return Err(e.to_string());
+        }
+        None => Bytes::new(),
+    };
+
+    Ok(HttpResponse::Ok().content_type(content_type).streaming(prepend(first, output)))
+}
+
+fn spawn_producer<F, Fut>(producer: F) -> BodyReceiver
+    where F: FnOnce(BodySender) -> Fut, Fut: Future<Output = Result<(), String>> + 'static
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let mut errors = tx.clone();
+    let task = producer(tx);
+
+    // bezi na workeru jako samostatny task, takze handler muze zacit odpovidat
+    actix_web::rt::spawn(async move {
+        if let Err(e) = task.await {
+            errors.send(Err(io::Error::other(e))).await.ok();
+        }
+    });
+
+    rx
+}
+
+fn prepend(
+    first: impl Into<Bytes>,
+    rest: BodyReceiver
+) -> impl futures::Stream<Item = Result<Bytes, io::Error>> {
+    let first: Bytes = first.into();
+    let first = if first.is_empty() { None } else { Some(Ok(first)) };
+
+    stream::iter(first).chain(rest)
+}
+
+// konec hlavicek - prvni `\r\n\r\n` nebo `\n\n`; vraci (delka hlavicek, delka oddelovace)
+fn find_header_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let crlf = buffer
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| (pos, 4));
+    let lf = buffer
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .map(|pos| (pos, 2));
+
+    match (crlf, lf) {
+        (Some(crlf), Some(lf)) => Some(if lf.0 < crlf.0 { lf } else { crlf }),
+        (crlf, lf) => crlf.or(lf),
+    }
+}
+
+fn build_headers(headers: &[u8]) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    let mut status_code = None;
+    let mut content_type = None;
+    let mut has_location = false;
+
+    for line in headers.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        // rozdeleni headeru na jmeno a hodnotu
+        let colon_pos = match line.iter().position(|b| *b == b':') {
+            Some(pos) => pos,
+            None => {
+                continue;
+            }
+        };
+        let name = String::from_utf8_lossy(&line[..colon_pos]).trim().to_string();
+        let value = line[colon_pos + 1..].trim_ascii();
+
+        // kontrola status kodu ("Status: 404 Not Found")
+        if name.eq_ignore_ascii_case("Status") {
+            let code = String::from_utf8_lossy(value)
+                .split_whitespace()
+                .next()
+                .and_then(|code| code.parse::<u16>().ok())
+                .unwrap_or(500);
+            status_code = Some(code);
+            continue;
+        }
+
+        let (name, value) = match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_bytes(value)) {
+            (Ok(name), Ok(value)) => (name, value),
+            _ => {
+                println!("neplatna hlavicka v CGI odpovedi: {}", String::from_utf8_lossy(line));
+                continue;
+            }
+        };
+
+        match name.as_str() {
+            "content-type" => {
+                content_type = Some(value);
+            }
+            // delku a kodovani prenosu resi actix - delce od scriptu neverime (ob_gzhandler,
+            // rucni header()) a po kompresi by stejne neplatila
+            "content-length" | "transfer-encoding" | "connection" => {}
+            _ => {
+                has_location |= name == "location";
+                // append, aby se zachovalo vice Set-Cookie hlavicek
+                builder.append_header((name, value));
+            }
+        }
+    }
+
+    // Location bez Status je podle CGI presmerovani
+    let status_code = status_code.unwrap_or(if has_location { 302 } else { 200 });
+
+    builder.status(StatusCode::from_u16(status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+    builder.insert_header((
+        "content-type",
+        content_type.unwrap_or_else(|| HeaderValue::from_static("text/html")),
+    ));
+
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::{ to_bytes, BodySize, MessageBody };
+    use actix_web::http::header;
+
+    async fn respond(chunks: &[&'static [u8]]) -> HttpResponse {
+        let chunks: Vec<Bytes> = chunks.iter().map(|c| Bytes::from_static(c)).collect();
+
+        stream_cgi_response(move |mut tx| async move {
+            for chunk in chunks {
+                tx.send(Ok(chunk)).await.map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn body(response: HttpResponse) -> Bytes {
+        to_bytes(response.into_body()).await.unwrap()
+    }
+
+    #[test]
+    fn header_end_accepts_crlf_and_lf() {
+        assert_eq!(find_header_end(b"A: 1\r\n\r\nbody"), Some((4, 4)));
+        assert_eq!(find_header_end(b"A: 1\n\nbody"), Some((4, 2)));
+        // body muze obsahovat druhy oddelovac, plati prvni
+        assert_eq!(find_header_end(b"A: 1\n\nx\r\n\r\n"), Some((4, 2)));
+        assert_eq!(find_header_end(b"A: 1\r\n"), None);
+    }
+
+    #[actix_web::test]
+    async fn lf_separated_headers() {
+        let response = respond(&[b"Status: 404 Not Found\nContent-Type: text/plain\n\nnic"]).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "text/plain");
+        assert_eq!(body(response).await, "nic");
+    }
+
+    #[actix_web::test]
+    async fn multiple_set_cookie_headers_are_kept() {
+        let response = respond(&[b"Set-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\nok"]).await;
+
+        let cookies: Vec<_> = response.headers().get_all(header::SET_COOKIE).collect();
+        assert_eq!(cookies, ["a=1", "b=2"]);
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "text/html");
+    }
+
+    #[actix_web::test]
+    async fn location_without_status_redirects() {
+        let response = respond(&[b"Location: /jinam\r\n\r\n"]).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/jinam");
+
+        let response = respond(&[b"Status: 301\r\nLocation: /jinam\r\n\r\n"]).await;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    }
+
+    #[actix_web::test]
+    async fn binary_body_split_across_chunks() {
+        let response = respond(&[
+            b"Content-Type: image/png\r\n\r",
+            b"\n\x89PNG\r\n\x1a\n\x00\xff",
+            b"\xfe\n\n\x00",
+        ]).await;
+
+        assert_eq!(response.headers().get(header::CONTENT_TYPE).unwrap(), "image/png");
+        assert_eq!(body(response).await, &b"\x89PNG\r\n\x1a\n\x00\xff\xfe\n\n\x00"[..]);
+    }
+
+    #[actix_web::test]
+    async fn script_content_length_is_ignored() {
+        let response = respond(&[b"Content-Length: 100\r\n\r\nkratsi"]).await;
+
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        // bez delky posle actix body chunked
+        assert_eq!(response.body().size(), BodySize::Stream);
+        assert_eq!(body(response).await, "kratsi");
+    }
+}
@@ -1,7 +1,9 @@
 use actix_web::{ web, HttpResponse, Responder };
 use serde::{ Deserialize };
 use std::sync::Arc;
-use crate::config::{ ServerConfig, ConfigManager };
+use crate::config::{ ServerConfig, ConfigManager, EtagMode };
+use crate::server::cache::ResponseCache;
+use crate::server::upstream::UpstreamRegistry;
 
 #[derive(Deserialize)]
 pub struct ConfigUpdateRequest {
@@ -59,6 +61,22 @@ pub async fn reload_config(config_manager: web::Data<Arc<ConfigManager>>) -> imp
     }
 }
 
+// stav upstream skupin pro proxy (dostupnost, aktivni spojeni, chyby)
+pub async fn get_upstreams(upstreams: web::Data<Arc<UpstreamRegistry>>) -> impl Responder {
+    match serde_json::to_string_pretty(&upstreams.status()) {
+        Ok(json) => { HttpResponse::Ok().content_type("application/json").body(json) }
+        Err(_) => { HttpResponse::InternalServerError().body("chyba pri serializaci stavu upstreamu") }
+    }
+}
+
+// pocty zasahu a minuti cache odpovedi a jeji zaplneni
+pub async fn get_cache_stats(cache: web::Data<Arc<ResponseCache>>) -> impl Responder {
+    match serde_json::to_string_pretty(&cache.stats()) {
+        Ok(json) => { HttpResponse::Ok().content_type("application/json").body(json) }
+        Err(_) => { HttpResponse::InternalServerError().body("chyba pri serializaci stavu cache") }
+    }
+}
+
 // pomocna funkce pro aktualizaci hodnot v konfiguraci
 fn update_config_value(
     config: &mut ServerConfig,
@@ -117,6 +135,18 @@ fn update_config_value(
 
                     config.file_extension_cache_times.insert(ext, seconds);
                 }
+                "etag" => {
+                    config.etag = match value {
+                        "metadata" | "weak" => EtagMode::Metadata,
+                        "content" | "strong" => EtagMode::Content,
+                        "off" => EtagMode::Off,
+                        _ => {
+                            return Err(
+                                "neplatna hodnota pro etag (metadata, content, off)".to_string()
+                            );
+                        }
+                    };
+                }
                 _ => {
                     return Err(format!("neplatny nastaveni cache: {}", key));
                 }
@@ -183,9 +213,16 @@ fn update_config_value(
                     config.static_root = value.to_string();
                 }
                 "max_file_size" => {
-                    config.max_file_size = value
-                        .parse::<usize>()
-                        .map_err(|_| "neplatna hodnota pro max_file_size".to_string())?;
+                    // prazdna hodnota limit vypne
+                    config.max_file_size = match value {
+                        "" | "none" => None,
+                        _ =>
+                            Some(
+                                value
+                                    .parse::<usize>()
+                                    .map_err(|_| "neplatna hodnota pro max_file_size".to_string())?
+                            ),
+                    };
                 }
                 _ => {
                     return Err(format!("neplatny nastaveni static souboru: {}", key));
@@ -0,0 +1,219 @@
+use ring::digest::{ Context, SHA256 };
+use std::collections::HashMap;
+use std::fs::{ self, Metadata };
+use std::io::{ self, Read };
+use std::path::{ Path, PathBuf };
+use std::sync::Mutex;
+use std::time::{ SystemTime, UNIX_EPOCH };
+use crate::config::EtagMode;
+
+// po prekroceni se ulozene hashe zahodi a pocitaji znovu
+const MAX_CONTENT_HASHES: usize = 10_000;
+
+lazy_static::lazy_static! {
+    // SHA-256 obsahu podle cesty - plati, dokud se nezmeni mtime a velikost
+    static ref CONTENT_HASHES: Mutex<HashMap<PathBuf, (SystemTime, u64, String)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// ETag souboru podle nastaveneho rezimu; prazdny retezec pro `off`
+pub fn generate_etag(
+    path: &Path,
+    metadata: &Metadata,
+    mode: EtagMode
+) -> Result<String, io::Error> {
+    match mode {
+        EtagMode::Metadata => Ok(metadata_etag(metadata)),
+        EtagMode::Content => content_etag(path, metadata),
+        EtagMode::Off => Ok(String::new()),
+    }
+}
+
+// "velikost-mtime-inode" hexadecimalne, soubor se vubec necte; silny jako u nginxu -
+// mtime v nanosekundach a inode se zmeni pri kazdem prepsani souboru, takze funguje
+// i pro If-Range
+fn metadata_etag(metadata: &Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        format!("\"{:x}-{:x}-{:x}\"", metadata.len(), modified, metadata.ino())
+    }
+
+    #[cfg(not(unix))]
+    {
+        format!("\"{:x}-{:x}\"", metadata.len(), modified)
+    }
+}
+
+// silny ETag z hashe obsahu; soubor se cte znovu jen po zmene mtime nebo velikosti
+fn content_etag(path: &Path, metadata: &Metadata) -> Result<String, io::Error> {
+    let modified = match metadata.modified() {
+        Ok(modified) => modified,
+        Err(_) => {
+            return hash_file(path);
+        }
+    };
+
+    if let Some((cached_modified, cached_size, etag)) = CONTENT_HASHES.lock().unwrap().get(path) {
+        if *cached_modified == modified && *cached_size == metadata.len() {
+            return Ok(etag.clone());
+        }
+    }
+
+    // hashuje se mimo zamek, ostatni requesty nemusi cekat
+    let etag = hash_file(path)?;
+
+    let mut hashes = CONTENT_HASHES.lock().unwrap();
+    if hashes.len() >= MAX_CONTENT_HASHES {
+        hashes.clear();
+    }
+    hashes.insert(path.to_path_buf(), (modified, metadata.len(), etag.clone()));
+
+    Ok(etag)
+}
+
+fn hash_file(path: &Path) -> Result<String, io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut context = Context::new(&SHA256);
+    let mut buffer = [0; 4096];
+
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        context.update(&buffer[..count]);
+    }
+
+    let digest = context.finish();
+    Ok(format!("\"{}\"", hex::encode(digest.as_ref())))
+}
+
+/// slaba verze ETagu pro jinou reprezentaci stejneho souboru (komprese)
+pub fn weak(etag: &str) -> String {
+    if etag.is_empty() || etag.starts_with("W/") {
+        etag.to_string()
+    } else {
+        format!("W/{}", etag)
+    }
+}
+
+/// If-None-Match (RFC 9110, 13.1.2) - `*` nebo seznam ETagu porovnanych slabe,
+/// tedy bez ohledu na `W/`
+pub fn none_match(header: &str, etag: &str) -> bool {
+    if header.trim() == "*" {
+        return true;
+    }
+    if etag.is_empty() {
+        return false;
+    }
+
+    let current = opaque_tag(etag);
+    parse_etags(header).into_iter().any(|tag| opaque_tag(tag) == current)
+}
+
+// ETag bez `W/`
+fn opaque_tag(etag: &str) -> &str {
+    etag.strip_prefix("W/").unwrap_or(etag)
+}
+
+// seznam ETagu oddelenych carkami; carka muze byt i uvnitr uvozovek,
+// proto se nedeli jen podle ni. Za neplatnou polozkou se cteni ukonci.
+fn parse_etags(header: &str) -> Vec<&str> {
+    let mut etags = Vec::new();
+    let mut rest = header;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            break;
+        }
+
+        let prefix = if rest.starts_with("W/") { 2 } else { 0 };
+        if !rest[prefix..].starts_with('"') {
+            break;
+        }
+
+        let end = match rest[prefix + 1..].find('"') {
+            Some(end) => prefix + 1 + end + 1,
+            None => {
+                break;
+            }
+        };
+
+        etags.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    etags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etag_of(path: &Path, mode: EtagMode) -> String {
+        generate_etag(path, &fs::metadata(path).unwrap(), mode).unwrap()
+    }
+
+    #[test]
+    fn metadata_etag_is_strong_and_follows_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("video.mp4");
+        fs::write(&path, "prvni").unwrap();
+
+        let first = etag_of(&path, EtagMode::Metadata);
+        assert!(first.starts_with('"') && first.ends_with('"'));
+        assert_eq!(etag_of(&path, EtagMode::Metadata), first);
+
+        fs::write(&path, "druha verze").unwrap();
+        assert_ne!(etag_of(&path, EtagMode::Metadata), first);
+    }
+
+    #[test]
+    fn content_etag_depends_only_on_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.txt");
+        let b = dir.path().join("b.txt");
+        fs::write(&a, "stejny obsah").unwrap();
+        fs::write(&b, "stejny obsah").unwrap();
+
+        assert_eq!(etag_of(&a, EtagMode::Content), etag_of(&b, EtagMode::Content));
+        assert!(etag_of(&a, EtagMode::Content).starts_with('"'));
+        assert_eq!(etag_of(&a, EtagMode::Off), "");
+    }
+
+    #[test]
+    fn none_match_compares_weakly() {
+        assert!(none_match("*", ""));
+        assert!(none_match("\"a\"", "W/\"a\""));
+        assert!(none_match("W/\"a\"", "\"a\""));
+        assert!(none_match("\"x\", W/\"a,b\"", "\"a,b\""));
+        assert!(!none_match("\"x\"", "\"a\""));
+        assert!(!none_match("\"a\"", ""));
+        assert_eq!(weak("\"a\""), "W/\"a\"");
+        assert_eq!(weak("W/\"a\""), "W/\"a\"");
+        assert_eq!(weak(""), "");
+    }
+
+    #[test]
+    fn legacy_mode_names_still_parse() {
+        #[derive(serde::Deserialize)]
+        struct Mode {
+            etag: EtagMode,
+        }
+        let parse = |value: &str| toml::from_str::<Mode>(&format!("etag = \"{}\"", value)).unwrap();
+
+        assert_eq!(parse("weak").etag, EtagMode::Metadata);
+        assert_eq!(parse("strong").etag, EtagMode::Content);
+        assert_eq!(parse("metadata").etag, EtagMode::Metadata);
+        assert_eq!(parse("content").etag, EtagMode::Content);
+    }
+}
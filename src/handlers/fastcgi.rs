@@ -0,0 +1,331 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+use actix_web::web::Bytes;
+use futures::SinkExt;
+use tokio::io::{ AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt };
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use crate::handlers::cgi_response::BodySender;
+
+// FastCGI protokol (verze 1)
+const FCGI_VERSION: u8 = 1;
+const FCGI_BEGIN_REQUEST: u8 = 1;
+const FCGI_END_REQUEST: u8 = 3;
+const FCGI_PARAMS: u8 = 4;
+const FCGI_STDIN: u8 = 5;
+const FCGI_STDOUT: u8 = 6;
+const FCGI_STDERR: u8 = 7;
+const FCGI_RESPONDER: u16 = 1;
+const FCGI_KEEP_CONN: u8 = 1;
+const FCGI_REQUEST_COMPLETE: u8 = 0;
+const MAX_RECORD_CONTENT: usize = 65535;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// na jednom spojeni bezi vzdy jen jeden request (php-fpm multiplexing nepodporuje),
+// soubezne requesty se rozkladaji mezi spojeni v poolu
+const REQUEST_ID: u16 = 1;
+
+trait Connection: AsyncRead + AsyncWrite + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Unpin> Connection for T {}
+
+type BoxedConnection = Box<dyn Connection>;
+
+// pool spojeni pro jednu adresu; kazdy actix worker ma vlastni,
+// protoze tokio sockety jsou vazane na runtime workeru
+struct FastCgiPool {
+    address: String,
+    size: usize,
+    idle: RefCell<Vec<BoxedConnection>>,
+    permits: Semaphore,
+}
+
+thread_local! {
+    static POOLS: RefCell<HashMap<String, Rc<FastCgiPool>>> = RefCell::new(HashMap::new());
+}
+
+// po zmene `php_fastcgi_pool_size` reloadem se pool pro adresu vytvori znovu;
+// probihajici requesty dobehnou na puvodnim, jeho spojeni se pak zavrou
+fn get_pool(address: &str, pool_size: usize) -> Rc<FastCgiPool> {
+    let pool_size = pool_size.max(1);
+
+    POOLS.with(|pools| {
+        let mut pools = pools.borrow_mut();
+
+        match pools.get(address) {
+            Some(pool) if pool.size == pool_size => pool.clone(),
+            _ => {
+                let pool = Rc::new(FastCgiPool {
+                    address: address.to_string(),
+                    size: pool_size,
+                    idle: RefCell::new(Vec::new()),
+                    permits: Semaphore::new(pool_size),
+                });
+                pools.insert(address.to_string(), pool.clone());
+                pool
+            }
+        }
+    })
+}
+
+/// spusti request pres FastCGI (php-fpm) na adrese `host:port` nebo `unix:/cesta/k/socketu`;
+/// stdout se prubezne posila do `stdout`; cely request vcetne cekani na volne
+/// spojeni je omezen `timeout`
+pub async fn execute(
+    address: &str,
+    pool_size: usize,
+    params: &HashMap<String, String>,
+    stdin: &[u8],
+    timeout: Duration,
+    stdout: &mut BodySender
+) -> Result<(), String> {
+    let pool = get_pool(address, pool_size);
+
+    match tokio::time::timeout(timeout, pool.execute(params, stdin, stdout)).await {
+        Ok(result) => result,
+        Err(_) => Err("chyba: FastCGI request prekrocil timeout".to_string()),
+    }
+}
+
+/// jeden request na novem spojeni (bez keep-alive); pripojeni se opakuje do
+/// `connect_timeout`, protoze cerstve spusteny worker jeste nemusi poslouchat
+pub async fn execute_single(
+    address: &str,
+    params: &HashMap<String, String>,
+    stdin: &[u8],
+    connect_timeout: Duration,
+    stdout: &mut BodySender
+) -> Result<(), String> {
+    let deadline = tokio::time::Instant::now() + connect_timeout;
+
+    let mut conn = loop {
+        match connect(address).await {
+            Ok(conn) => {
+                break conn;
+            }
+            Err(e) if tokio::time::Instant::now() >= deadline => {
+                return Err(e);
+            }
+            Err(_) => tokio::time::sleep(CONNECT_RETRY_DELAY).await,
+        }
+    };
+
+    run_request(&mut conn, params, stdin, false, stdout).await.map_err(|e| e.message)
+}
+
+impl FastCgiPool {
+    async fn execute(
+        &self,
+        params: &HashMap<String, String>,
+        stdin: &[u8],
+        stdout: &mut BodySender
+    ) -> Result<(), String> {
+        let _permit = self.permits
+            .acquire()
+            .await
+            .map_err(|_| "FastCGI pool byl uzavren".to_string())?;
+
+        // nejdriv zkusime spojeni z poolu; pokud ho server mezitim zavrel a nic jsme
+        // jeste neprijali, zopakujeme request na novem spojeni
+        let idle = self.idle.borrow_mut().pop();
+        if let Some(mut conn) = idle {
+            match run_request(&mut conn, params, stdin, true, stdout).await {
+                Ok(()) => {
+                    self.idle.borrow_mut().push(conn);
+                    return Ok(());
+                }
+                Err(RequestError { message, response_started: true }) => {
+                    return Err(message);
+                }
+                Err(_) => {}
+            }
+        }
+
+        let mut conn = connect(&self.address).await?;
+        run_request(&mut conn, params, stdin, true, stdout).await.map_err(|e| e.message)?;
+        self.idle.borrow_mut().push(conn);
+
+        Ok(())
+    }
+}
+
+async fn connect(address: &str) -> Result<BoxedConnection, String> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let stream = tokio::net::UnixStream::connect(path).await
+                .map_err(|e| format!("chyba pri pripojeni k FastCGI {}: {}", address, e))?;
+            return Ok(Box::new(stream));
+        }
+
+        #[cfg(not(unix))]
+        {
+            return Err(format!("unix socket neni na teto platforme podporovan: {}", path));
+        }
+    }
+
+    let stream = TcpStream::connect(address).await.map_err(|e|
+        format!("chyba pri pripojeni k FastCGI {}: {}", address, e)
+    )?;
+    stream.set_nodelay(true).ok();
+
+    Ok(Box::new(stream))
+}
+
+struct RequestError {
+    message: String,
+    /// server uz zacal odpovidat, request nelze bezpecne zopakovat
+    response_started: bool,
+}
+
+async fn run_request(
+    conn: &mut BoxedConnection,
+    params: &HashMap<String, String>,
+    stdin: &[u8],
+    keep_conn: bool,
+    stdout: &mut BodySender
+) -> Result<(), RequestError> {
+    let fail = |message: String, response_started: bool| RequestError {
+        message,
+        response_started,
+    };
+
+    let mut request = Vec::with_capacity(1024 + stdin.len());
+
+    // BEGIN_REQUEST - role responder, pro pool nechavame spojeni otevrene
+    let mut begin = [0u8; 8];
+    begin[..2].copy_from_slice(&FCGI_RESPONDER.to_be_bytes());
+    if keep_conn {
+        begin[2] = FCGI_KEEP_CONN;
+    }
+    write_record(&mut request, FCGI_BEGIN_REQUEST, &begin);
+
+    let mut encoded_params = Vec::new();
+    for (name, value) in params {
+        encode_param(&mut encoded_params, name.as_bytes(), value.as_bytes());
+    }
+    write_stream(&mut request, FCGI_PARAMS, &encoded_params);
+    write_stream(&mut request, FCGI_STDIN, stdin);
+
+    conn
+        .write_all(&request)
+        .await
+        .map_err(|e| fail(format!("chyba pri zapisu do FastCGI: {}", e), false))?;
+    conn.flush().await.map_err(|e| fail(format!("chyba pri zapisu do FastCGI: {}", e), false))?;
+
+    let mut stderr = Vec::new();
+    let mut response_started = false;
+
+    loop {
+        let (record_type, content) = read_record(conn).await.map_err(|e|
+            fail(e, response_started)
+        )?;
+        response_started = true;
+
+        match record_type {
+            FCGI_STDOUT if content.is_empty() => {}
+            FCGI_STDOUT => {
+                // kdyz klient odesel, spojeni uz nejde vratit do poolu (zbytek odpovedi neprecteme)
+                stdout
+                    .send(Ok(Bytes::from(content))).await
+                    .map_err(|_| fail("klient ukoncil spojeni".to_string(), true))?;
+            }
+            FCGI_STDERR => stderr.extend_from_slice(&content),
+            FCGI_END_REQUEST => {
+                if !stderr.is_empty() {
+                    println!("chyba pri behu PHP (FastCGI): {}", String::from_utf8_lossy(&stderr));
+                }
+
+                if content.len() >= 5 && content[4] != FCGI_REQUEST_COMPLETE {
+                    return Err(
+                        fail(format!("FastCGI server odmitl request (status {})", content[4]), true)
+                    );
+                }
+                return Ok(());
+            }
+            // ostatni typy zaznamu ignorujeme
+            _ => {}
+        }
+    }
+}
+
+// stream zaznamy (PARAMS, STDIN) se rozdeli po 64 KB a ukonci prazdnym zaznamem
+fn write_stream(buffer: &mut Vec<u8>, record_type: u8, content: &[u8]) {
+    for chunk in content.chunks(MAX_RECORD_CONTENT) {
+        write_record(buffer, record_type, chunk);
+    }
+    write_record(buffer, record_type, &[]);
+}
+
+fn write_record(buffer: &mut Vec<u8>, record_type: u8, content: &[u8]) {
+    let padding = (8 - (content.len() % 8)) % 8;
+
+    buffer.push(FCGI_VERSION);
+    buffer.push(record_type);
+    buffer.extend_from_slice(&REQUEST_ID.to_be_bytes());
+    buffer.extend_from_slice(&(content.len() as u16).to_be_bytes());
+    buffer.push(padding as u8);
+    buffer.push(0);
+    buffer.extend_from_slice(content);
+    buffer.extend(std::iter::repeat_n(0u8, padding));
+}
+
+// delky do 127 se koduji jednim bajtem, delsi ctyrmi s nastavenym hornim bitem
+fn encode_param(buffer: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    for len in [name.len(), value.len()] {
+        if len < 128 {
+            buffer.push(len as u8);
+        } else {
+            buffer.extend_from_slice(&((len as u32) | 0x8000_0000).to_be_bytes());
+        }
+    }
+    buffer.extend_from_slice(name);
+    buffer.extend_from_slice(value);
+}
+
+async fn read_record(conn: &mut BoxedConnection) -> Result<(u8, Vec<u8>), String> {
+    let mut header = [0u8; 8];
+    conn.read_exact(&mut header).await.map_err(|e| format!("chyba pri cteni z FastCGI: {}", e))?;
+
+    let record_type = header[1];
+    let content_length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let padding_length = header[6] as usize;
+
+    let mut content = vec![0u8; content_length + padding_length];
+    conn.read_exact(&mut content).await.map_err(|e| format!("chyba pri cteni z FastCGI: {}", e))?;
+    content.truncate(content_length);
+
+    Ok((record_type, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_follows_reloaded_size() {
+        let pool = get_pool("127.0.0.1:9000", 4);
+        assert!(Rc::ptr_eq(&pool, &get_pool("127.0.0.1:9000", 4)));
+        assert_eq!(pool.permits.available_permits(), 4);
+
+        let resized = get_pool("127.0.0.1:9000", 2);
+        assert!(!Rc::ptr_eq(&pool, &resized));
+        assert_eq!(resized.permits.available_permits(), 2);
+
+        let other = get_pool("unix:/run/php-fpm.sock", 2);
+        assert_eq!(other.address, "unix:/run/php-fpm.sock");
+        assert!(Rc::ptr_eq(&resized, &get_pool("127.0.0.1:9000", 2)));
+    }
+
+    #[test]
+    fn long_params_use_four_byte_length() {
+        let mut buffer = Vec::new();
+        encode_param(&mut buffer, b"QUERY_STRING", &[b'a'; 200]);
+
+        assert_eq!(buffer[0], 12);
+        assert_eq!(&buffer[1..5], &(200u32 | 0x8000_0000).to_be_bytes());
+        assert_eq!(&buffer[5..17], b"QUERY_STRING");
+        assert_eq!(buffer.len(), 17 + 200);
+    }
+}
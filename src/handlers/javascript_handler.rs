@@ -1,81 +1,196 @@
-use actix_web::{ web, HttpResponse, Responder, HttpRequest };
+use actix_web::{ web, web::Bytes, HttpResponse, HttpRequest };
+use actix_web::http::header;
 use std::fs;
+use std::io;
 use std::path::{ Path, PathBuf };
 use minify_js::{ minify, TopLevelMode };
 use std::time::{ UNIX_EPOCH };
 use std::sync::Arc;
-use crate::config::ConfigManager;
+use crate::config::{ ConfigManager, MatchedLocation };
+use crate::handlers::access::log_violation;
+use crate::handlers::etag::{ generate_etag, weak };
+use crate::handlers::static_files::{ cached_response, not_modified };
+use crate::utils::path_resolver::{ contain, resolve };
+use crate::server::cache::{ CacheKey, CachedResponse, ResponseCache };
+use crate::utils::compression::{ compress, ranked, CompressionLevels, Encoding };
 
 pub async fn handle_js(
     req: HttpRequest,
     config_manager: web::Data<Arc<ConfigManager>>
-) -> impl Responder {
-    let config = config_manager.get_config();
+) -> HttpResponse {
+    let config = config_manager.get_request_config(&req);
 
-    let path = req.path();
+    let raw_path = match MatchedLocation::of(&req) {
+        Some(matched) => matched.relative_path,
+        None => req.path().trim_start_matches("/js/").to_string(),
+    };
 
-    let file_path = path.trim_start_matches("/js/");
+    // stejna kontrola cesty jako u statickych souboru
+    let js_root = PathBuf::from(&config.js_root_dir);
+    let (file_path, full_path) = match resolve(&js_root, &raw_path) {
+        Ok(resolved) => (resolved.relative, resolved.full),
+        Err(e) => {
+            log_violation(&req, &js_root.join(&raw_path), &e);
+            return HttpResponse::BadRequest().body("neplatna cesta");
+        }
+    };
+    let file_path = file_path.as_str();
 
-    let full_path = PathBuf::from(&config.js_root_dir).join(file_path);
+    // symlink ven z korene se tvari jako neexistujici soubor
+    if let Err(e) = contain(&js_root, &full_path) {
+        log_violation(&req, &full_path, &e);
+        return HttpResponse::NotFound().body(format!("JavaScript file not found: {}", file_path));
+    }
 
     if !Path::new(&full_path).exists() || !Path::new(&full_path).is_file() {
         return HttpResponse::NotFound().body(format!("JavaScript file not found: {}", file_path));
     }
 
-    match fs::read_to_string(&full_path) {
-        Ok(content) => {
-            let js_content = if config.development_mode || !config.js_minify {
-                // v development modu nebo pokud je minifikace zakazana, vracime puvodni obsah
-                content
-            } else {
-                // minifikujeme
-                match minify_javascript(&content) {
-                    Ok(min) => min,
-                    Err(_) => content, // pokud selze, vracime puvodni obsah
-                }
-            };
-
-            // ziskame timestamp pro posledni upravu souboru
-            let last_modified = get_last_modified(&full_path).unwrap_or_else(|| "".to_string());
-
-            // sestavime response
-            let mut builder = HttpResponse::Ok();
-            let mut response = builder.content_type("application/javascript");
-
-            // pridame cache headery
-            if !config.development_mode && config.cache_enabled {
-                // Get extension for cache duration lookup
-                let ext = Path::new(file_path)
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                let cache_seconds = config.get_cache_duration(&ext);
-
-                if cache_seconds > 0 {
-                    response = response.insert_header((
-                        "Cache-Control",
-                        format!("public, max-age={}", cache_seconds),
-                    ));
-                }
-            } else {
-                // zadny cachovani v development modu
-                response = response.insert_header(("Cache-Control", "no-store, max-age=0"));
-            }
+    let metadata = match fs::metadata(&full_path) {
+        Ok(metadata) => metadata,
+        Err(_) => {
+            return HttpResponse::InternalServerError().body(
+                format!("Failed to read JavaScript file: {}", file_path)
+            );
+        }
+    };
 
-            // pridame last-modified header
-            if !last_modified.is_empty() {
-                response = response.insert_header(("Last-Modified", last_modified));
-            }
+    // v development modu nebo bez cache se nic necachuje
+    let cache_control = if config.development_mode || !config.cache_enabled {
+        Some("no-store, max-age=0".to_string())
+    } else {
+        let ext = Path::new(file_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
 
-            response.body(js_content)
+        match config.get_cache_duration(ext) {
+            0 => None,
+            cache_seconds => Some(format!("public, max-age={}", cache_seconds)),
         }
-        Err(_) => {
-            HttpResponse::InternalServerError().body(
+    };
+
+    // kompresi delame sami, aby se v cache drzela hotova varianta pro kazde kodovani
+    let should_compress =
+        config.enable_compression && metadata.len() > (config.min_size_to_compress as u64);
+    let accepted = ranked(
+        req.headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok()),
+        &config.compression_preference
+    );
+    let encoding = match accepted.first() {
+        Some(encoding) if should_compress && *encoding != Encoding::Identity => Some(*encoding),
+        _ => None,
+    };
+    let minify = !config.development_mode && config.js_minify;
+
+    // minifikovany obsah neni bajtove shodny se souborem - ETag je vzdy slaby
+    let etag = if config.development_mode {
+        String::new()
+    } else {
+        weak(&generate_etag(&full_path, &metadata, config.etag).unwrap_or_default())
+    };
+    let last_modified = get_last_modified(&full_path).unwrap_or_default();
+
+    if let Some(response) = not_modified(&req, &etag, &last_modified) {
+        return response;
+    }
+
+    // minifikovany a zkomprimovany vysledek se drzi v cache, dokud se soubor nezmeni
+    let cache = ResponseCache::of(&req).filter(|_| !config.development_mode);
+    let cache_key = match cache {
+        Some(cache) if cache.accepts(metadata.len()) => {
+            let variant = format!(
+                "js|{}|{}|{}|{}|{}",
+                encoding.unwrap_or(Encoding::Identity).as_str(),
+                cache_control.as_deref().unwrap_or(""),
+                minify,
+                config.development_mode,
+                config.etag.as_str()
+            );
+            CacheKey::for_file(&full_path, &metadata, variant).map(|key| (cache, key))
+        }
+        _ => None,
+    };
+
+    if let Some((cache, key)) = &cache_key {
+        if let Some(cached) = cache.get(key) {
+            return cached_response(&req, &cached);
+        }
+    }
+
+    // cteni, minifikace i komprese bezi mimo worker
+    let path = full_path.clone();
+    let levels = config.compression_levels;
+    let body = match web::block(move || render_js(&path, minify, encoding, &levels)).await {
+        Ok(Ok(body)) => Bytes::from(body),
+        _ => {
+            return HttpResponse::InternalServerError().body(
                 format!("Failed to read JavaScript file: {}", file_path)
-            )
+            );
         }
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/javascript");
+
+    if let Some(cache_control) = &cache_control {
+        response.insert_header((header::CACHE_CONTROL, cache_control.as_str()));
+    }
+    if !etag.is_empty() {
+        response.insert_header((header::ETAG, etag.as_str()));
+    }
+    if !last_modified.is_empty() {
+        response.insert_header((header::LAST_MODIFIED, last_modified.as_str()));
+    }
+    if should_compress {
+        response.insert_header((header::VARY, "Accept-Encoding"));
+    }
+    if let Some(encoding) = encoding {
+        response.insert_header((header::CONTENT_ENCODING, encoding.as_str()));
+    }
+
+    let response = response.body(body.clone());
+
+    if let Some((cache, key)) = cache_key {
+        cache.insert(
+            key,
+            Arc::new(CachedResponse {
+                body,
+                etag,
+                last_modified,
+                headers: response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            })
+        );
+    }
+
+    response
+}
+
+// obsah souboru po minifikaci (pri chybe puvodni) a pripadne kompresi
+fn render_js(
+    path: &Path,
+    minify: bool,
+    encoding: Option<Encoding>,
+    levels: &CompressionLevels
+) -> Result<Vec<u8>, io::Error> {
+    let content = fs::read_to_string(path)?;
+
+    let content = if minify {
+        // pokud minifikace selze, vracime puvodni obsah
+        minify_javascript(&content).unwrap_or(content)
+    } else {
+        content
+    };
+
+    match encoding {
+        Some(encoding) => compress(&mut content.as_bytes(), Vec::new(), encoding, levels),
+        None => Ok(content.into_bytes()),
     }
 }
 
@@ -96,19 +211,79 @@ fn minify_javascript(js_code: &str) -> Result<String, String> {
 }
 
 // pomocna funkce pro ziskani casu posledni upravy souboru
-fn get_last_modified(path: &PathBuf) -> Option<String> {
+fn get_last_modified(path: &Path) -> Option<String> {
     if let Ok(metadata) = fs::metadata(path) {
         if let Ok(modified) = metadata.modified() {
             if let Ok(duration) = modified.duration_since(UNIX_EPOCH) {
                 let timestamp = duration.as_secs();
                 // prevedeme timestamp na UTC a potom formatujeme na RFC 1123
-                let dt = chrono::DateTime::<chrono::Utc>::from_utc(
-                    chrono::NaiveDateTime::from_timestamp_opt(timestamp as i64, 0)?,
-                    chrono::Utc
-                );
+                let dt = chrono::DateTime::from_timestamp(timestamp as i64, 0)?;
                 return Some(dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
             }
         }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use actix_web::{ test as actix_test, App };
+
+    #[actix_web::test]
+    async fn cached_variant_per_encoding_with_validators() {
+        let dir = tempfile::tempdir().unwrap();
+        let js_root = dir.path().join("js");
+        fs::create_dir(&js_root).unwrap();
+        fs::write(js_root.join("app.js"), "function secti(a, b) { return a + b; }\n".repeat(100))
+            .unwrap();
+
+        let config_path = dir.path().join("server.toml");
+        let config = ServerConfig {
+            js_root_dir: js_root.to_string_lossy().to_string(),
+            ..ServerConfig::default()
+        };
+        config.save(config_path.to_str().unwrap()).unwrap();
+        let config_manager = Arc::new(ConfigManager::new(config_path.to_str().unwrap()).unwrap());
+        let cache = ResponseCache::new(1024 * 1024);
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(config_manager))
+                .app_data(web::Data::new(cache.clone()))
+                .route("/js/{tail:.*}", web::get().to(handle_js))
+        ).await;
+        let get = |encoding: &str| {
+            actix_test::TestRequest::get()
+                .uri("/js/app.js")
+                .insert_header((header::ACCEPT_ENCODING, encoding))
+                .to_request()
+        };
+
+        let first = actix_test::call_service(&app, get("gzip")).await;
+        assert_eq!(first.status(), 200);
+        assert_eq!(first.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert!(first.headers().get(header::ETAG).unwrap().to_str().unwrap().starts_with("W/"));
+        let last_modified = first.headers().get(header::LAST_MODIFIED).unwrap().clone();
+        let first_body = actix_test::read_body(first).await;
+
+        // druhy request se stejnym kodovanim jde z cache i s hlavickami
+        let second = actix_test::call_service(&app, get("gzip")).await;
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(second.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(second.headers().get(header::LAST_MODIFIED).unwrap(), &last_modified);
+        assert_eq!(actix_test::read_body(second).await, first_body);
+
+        // jine kodovani je jina varianta
+        let identity = actix_test::call_service(&app, get("identity")).await;
+        assert!(identity.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(cache.stats().entries, 2);
+
+        let conditional = actix_test::TestRequest::get()
+            .uri("/js/app.js")
+            .insert_header((header::IF_MODIFIED_SINCE, last_modified))
+            .to_request();
+        assert_eq!(actix_test::call_service(&app, conditional).await.status(), 304);
+    }
+}
@@ -3,9 +3,20 @@ pub mod php_handler;
 pub mod javascript_handler;
 pub mod config_handler;
 pub mod websocket_handler;
+pub mod proxy_handler;
+pub mod fastcgi;
+pub mod php_pool;
+pub mod php_process;
+pub mod cgi_response;
+pub mod cgi_env;
+pub mod range;
+pub mod etag;
+pub mod autoindex;
+pub mod access;
 
 pub use static_files::serve_static_file;
 pub use php_handler::handle_php;
 pub use javascript_handler::handle_js;
-pub use config_handler::{ get_config, update_config, reload_config };
+pub use config_handler::{ get_config, update_config, reload_config, get_upstreams, get_cache_stats };
 pub use websocket_handler::websocket_handler;
+pub use proxy_handler::handle_proxy;
@@ -1,77 +1,131 @@
-use actix_web::{ web, HttpRequest, HttpResponse, Responder };
-use actix_web::http::StatusCode;
-use std::process::{ Command, Stdio };
-use std::io::{ Write, Read };
-use std::path::{ Path, PathBuf };
+use actix_web::{ web, HttpRequest, HttpResponse };
+use tokio::process::Command;
+use std::path::Path;
 use std::collections::HashMap;
 use std::time::Duration;
 use futures::StreamExt;
-use wait_timeout::ChildExt;
 use std::sync::Arc;
 use std::fs;
-use crate::config::ConfigManager;
+use crate::config::{ ConfigManager, MatchedLocation, PhpMode, ServerConfig };
+use crate::handlers::{ fastcgi, php_pool, php_process };
+use crate::handlers::cgi_env::{
+    build_env,
+    cgi_path,
+    directory_index,
+    resolve_target,
+    try_files,
+    CgiRequestInfo,
+    PhpTarget,
+    ScriptInfo,
+};
+use crate::handlers::access::{ check_access, log_violation, AccessDenied };
+use crate::handlers::static_files::{ redirect_to_directory, serve_file };
+use crate::utils::path_resolver::{ contain, resolve };
+use crate::handlers::cgi_response::{ stream_body, stream_cgi_response, BodySender };
 
 const MAX_REQUEST_SIZE: usize = 8 * 1024 * 1024; // 8MB
 
 pub async fn handle_php(
     req: HttpRequest,
-    mut payload: web::Payload,
+    payload: web::Payload,
     config_manager: web::Data<Arc<ConfigManager>>
-) -> impl Responder {
-    let config = config_manager.get_config();
+) -> HttpResponse {
+    let config = config_manager.get_request_config(&req);
 
     if !config.php_enabled {
         return HttpResponse::NotFound().body("PHP processing is disabled");
     }
 
-    // extrahujeme cestu z requestu
-    let path = req.path();
-    let script_path = path.trim_start_matches("/php/");
+    // najdeme script v php_root_dir, zbytek cesty za nim je PATH_INFO (/php/index.php/foo/bar)
+    let (url_prefix, raw_path) = match MatchedLocation::of(&req) {
+        Some(matched) => (matched.url_prefix, matched.relative_path),
+        None => ("/php".to_string(), req.path().strip_prefix("/php").unwrap_or(req.path()).to_string()),
+    };
+    let php_root_dir = Path::new(&config.php_root_dir);
 
-    // sestrojime celou cestu k PHP scriptu
-    let script_filename = if script_path.is_empty() {
-        format!("{}/index.php", config.php_root_dir)
-    } else {
-        format!("{}/{}", config.php_root_dir, script_path)
+    // dekodovani a kontrola cesty stejne jako u statickych souboru
+    let (script_path, directory) = match resolve(php_root_dir, &raw_path) {
+        Ok(resolved) => (resolved.relative, resolved.full),
+        Err(e) => {
+            log_violation(&req, &php_root_dir.join(&raw_path), &e);
+            return HttpResponse::BadRequest().body("neplatna cesta");
+        }
+    };
+
+    // adresar s indexem bez lomitka na konci (/php/admin -> /php/admin/)
+    if directory.is_dir() && directory_index(&directory, &config.index_files).is_some() {
+        if let Some(response) = redirect_to_directory(&req) {
+            return response;
+        }
+    }
+
+    let index_files = &config.index_files;
+    let target = match &config.php_front_controller {
+        Some(front_controller) => {
+            try_files(php_root_dir, &url_prefix, &script_path, front_controller, index_files)
+        }
+        None => resolve_target(php_root_dir, &url_prefix, &script_path, index_files),
     };
 
-    // zkontrolujeme jestli PHP script existuje
-    let script_path = PathBuf::from(&script_filename);
-    if !script_path.exists() || !script_path.is_file() {
-        return HttpResponse::NotFound().body(format!("PHP script not found: {}", script_filename));
+    match target {
+        Some(target) => serve_php_target(req, payload, config, target).await,
+        None => HttpResponse::NotFound().body(format!("PHP script not found: {}", req.path())),
     }
+}
 
-    let uses_php_tags = check_php_tags(&script_path);
-
-    // extrahujeme query string
-    let query_string = req.query_string();
-
-    // sestavime environment variables pro PHP-CGI
-    let mut env_vars = HashMap::new();
-    env_vars.insert("SCRIPT_FILENAME".to_string(), script_filename.clone());
-    env_vars.insert("SCRIPT_NAME".to_string(), format!("/php/{}", script_path.display()));
-    env_vars.insert("REQUEST_METHOD".to_string(), req.method().to_string());
-    env_vars.insert("QUERY_STRING".to_string(), query_string.to_string());
-    env_vars.insert("REQUEST_URI".to_string(), req.uri().to_string());
-    env_vars.insert("SERVER_NAME".to_string(), req.connection_info().host().to_string());
-    env_vars.insert("SERVER_PROTOCOL".to_string(), "HTTP/1.1".to_string());
-    env_vars.insert("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string());
-
-    // pridame environment variables pro debugovani, kdyz je zapnuty development mode
-    if config.development_mode {
-        env_vars.insert("DEVELOPMENT_MODE".to_string(), "1".to_string());
+/// obslouzi vysledek `try_files` - staticky soubor posle, PHP script spusti
+pub async fn serve_php_target(
+    req: HttpRequest,
+    payload: web::Payload,
+    config: ServerConfig,
+    target: PhpTarget
+) -> HttpResponse {
+    // symlink ven z korene by jinak spustil nebo poslal cizi soubor
+    let contained = match &target {
+        PhpTarget::StaticFile(path) => contain(Path::new(&config.php_root_dir), path),
+        PhpTarget::Script(script) => contain(&script.document_root, &script.script_filename),
+    };
+    if let Err(e) = contained {
+        let path = match &target {
+            PhpTarget::StaticFile(path) => path,
+            PhpTarget::Script(script) => &script.script_filename,
+        };
+        log_violation(&req, path, &e);
+        return HttpResponse::NotFound().body(format!("PHP script not found: {}", req.path()));
     }
 
-    // pridame environment variables pro HTTP headers
-    for (header_name, header_value) in req.headers() {
-        let header_env_name = format!(
-            "HTTP_{}",
-            header_name.to_string().replace("-", "_").to_uppercase()
-        );
-        if let Ok(value_str) = header_value.to_str() {
-            env_vars.insert(header_env_name, value_str.to_string());
+    match target {
+        PhpTarget::StaticFile(path) => {
+            // staticky soubor z php_root_dir podleha deny_patterns stejne jako ve static_root
+            // (`/wp-config.php.bak` by se jinak poslal jako zdrojak)
+            let php_root_dir = Path::new(&config.php_root_dir);
+            let relative = fs::canonicalize(php_root_dir).ok().and_then(|root| {
+                let relative = path.strip_prefix(root).ok()?;
+                Some(relative.to_str()?.replace('\\', "/"))
+            });
+            let allowed = match relative {
+                Some(relative) => check_access(php_root_dir, &relative, &config),
+                None => Err(AccessDenied::OutsideRoot(path.clone())),
+            };
+            if let Err(denied) = allowed {
+                log_violation(&req, &path, &denied);
+                return HttpResponse::NotFound().body(format!("soubor nenalezen: {}", req.path()));
+            }
+
+            serve_file(&req, &config, php_root_dir, &path)
         }
+        PhpTarget::Script(script) => run_php_script(req, payload, config, script).await,
     }
+}
+
+async fn run_php_script(
+    req: HttpRequest,
+    mut payload: web::Payload,
+    config: ServerConfig,
+    script: ScriptInfo
+) -> HttpResponse {
+    let script_filename = cgi_path(&script.script_filename);
+    let uses_php_tags = check_php_tags(&script.script_filename);
 
     // pridame environment variables pro POST, PUT, atd...
     let mut request_body = Vec::new();
@@ -90,49 +144,61 @@ pub async fn handle_php(
         }
     }
 
-    // pridame CONTENT_LENGTH a CONTENT_TYPE do environment variables
-    if !request_body.is_empty() {
-        env_vars.insert("CONTENT_LENGTH".to_string(), request_body.len().to_string());
+    // sestavime CGI environment variables (stejne pro PHP-CGI, php.exe i FastCGI)
+    let mut request_info = CgiRequestInfo::from_request(&req);
+    request_info.content_length = request_body.len();
+    let env_vars = build_env(&request_info, &script, &config);
 
-        // Try to determine content type from headers
-        if let Some(content_type) = req.headers().get("content-type") {
-            if let Ok(content_type_str) = content_type.to_str() {
-                env_vars.insert("CONTENT_TYPE".to_string(), content_type_str.to_string());
-            }
+    let timeout = Duration::from_secs(config.php_timeout);
+
+    // v rezimu pool/fastcgi jdou vsechny scripty pres FastCGI
+    if config.php_mode != PhpMode::Spawn {
+        if config.php_mode == PhpMode::Fastcgi {
+            let address = config.php_fastcgi_address.clone();
+            let pool_size = config.php_fastcgi_pool_size;
+
+            return stream_cgi_response(move |mut stdout| async move {
+                fastcgi::execute(&address, pool_size, &env_vars, &request_body, timeout, &mut stdout).await
+            }).await;
         }
+
+        let pool = php_pool::get_pool(&config);
+        return stream_cgi_response(move |mut stdout| async move {
+            pool.execute(&env_vars, &request_body, timeout, &mut stdout).await
+        }).await;
     }
 
     if uses_php_tags {
         // Pro soubory začínající <?php použijeme php.exe
-        let php_response = execute_php_exe(
-            &script_filename,
-            env_vars,
-            &request_body,
-            &config.php_exe_path,
-            config.php_timeout
-        ).await;
+        let php_exe_path = config.php_exe_path.clone();
+        let php_response = stream_body("text/html", move |mut stdout| async move {
+            execute_php_exe(
+                &script_filename,
+                env_vars,
+                &request_body,
+                &php_exe_path,
+                timeout,
+                &mut stdout
+            ).await
+        }).await;
 
         match php_response {
-            Ok(response) => { HttpResponse::Ok().content_type("text/html").body(response) }
+            Ok(response) => response,
             Err(err) => { HttpResponse::InternalServerError().body(format!("php error: {}", err)) }
         }
     } else {
-        // Pro ostatní PHP soubory použijeme CGI
-        let php_response = execute_php_cgi(
-            &script_filename,
-            env_vars,
-            &request_body,
-            &config.php_cgi_path,
-            config.php_timeout
-        ).await;
-
-        match php_response {
-            Ok(response) => {
-                // parsujeme PHP response (header a body)
-                parse_php_response(response)
-            }
-            Err(err) => { HttpResponse::InternalServerError().body(format!("php error: {}", err)) }
-        }
+        // Pro ostatní PHP soubory použijeme CGI, hlavicky a body parsuje stream_cgi_response
+        let php_cgi_path = config.php_cgi_path.clone();
+        stream_cgi_response(move |mut stdout| async move {
+            execute_php_cgi(
+                &script_filename,
+                env_vars,
+                &request_body,
+                &php_cgi_path,
+                timeout,
+                &mut stdout
+            ).await
+        }).await
     }
 }
 
@@ -150,15 +216,11 @@ async fn execute_php_cgi(
     env_vars: HashMap<String, String>,
     request_body: &[u8],
     php_cgi_path: &str,
-    timeout_seconds: u64
-) -> Result<String, String> {
+    timeout: Duration,
+    stdout: &mut BodySender
+) -> Result<(), String> {
     // vytvorime PHP-CGI proces pro zpracovani PHP scriptu
     let mut command = Command::new(php_cgi_path);
-
-    // nastavime vlastnosti pro PHP-CGI
-    command.stdin(Stdio::piped());
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
     command.current_dir(Path::new(script_filename).parent().unwrap_or(Path::new(".")));
 
     // nastavime environment variables
@@ -166,58 +228,7 @@ async fn execute_php_cgi(
         command.env(key, value);
     }
 
-    // spawnneme process az po nastaveni environment variables
-    let mut child = command.spawn().map_err(|e| format!("chyba pri spousteni PHP-CGI: {}", e))?;
-
-    if !request_body.is_empty() {
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(request_body)
-                .map_err(|e| format!("chyba pri zapisovani do stdin PHP-CGI: {}", e))?;
-        }
-    }
-
-    // nastvime timeout pro PHP-CGI proces
-    let timeout = Duration::from_secs(timeout_seconds);
-    match child.wait_timeout(timeout) {
-        Ok(status_opt) => {
-            match status_opt {
-                Some(status) => {
-                    if !status.success() {
-                        return Err(format!("chyba pri behu PHP-CGI: {}", status));
-                    }
-                }
-                None => {
-                    // Process timeout
-                    child.kill().ok();
-                    return Err("chyba: PHP-CGI proces prekrocil timeout".to_string());
-                }
-            }
-        }
-        Err(e) => {
-            return Err(format!("chyba pri cekani na PHP-CGI: {}", e));
-        }
-    }
-
-    // sebereme output
-    let mut output = String::new();
-    child.stdout
-        .unwrap()
-        .read_to_string(&mut output)
-        .map_err(|e| format!("chyba pri cteni PHP-CGI stdout: {}", e))?;
-
-    // sebereme error output
-    let mut error_output = String::new();
-    child.stderr
-        .unwrap()
-        .read_to_string(&mut error_output)
-        .map_err(|e| format!("chyba pri cteni PHP-CGI stderr: {}", e))?;
-
-    if !error_output.is_empty() {
-        println!("chyba pri behu PHP-CGI: {}", error_output);
-    }
-
-    Ok(output)
+    php_process::run(command, request_body, timeout, "PHP-CGI", stdout).await
 }
 
 // Nová funkce pro spuštění PHP pomocí php.exe
@@ -226,16 +237,12 @@ async fn execute_php_exe(
     env_vars: HashMap<String, String>,
     request_body: &[u8],
     php_exe_path: &str,
-    timeout_seconds: u64
-) -> Result<String, String> {
+    timeout: Duration,
+    stdout: &mut BodySender
+) -> Result<(), String> {
     // vytvorime PHP proces pro zpracovani PHP scriptu
     let mut command = Command::new(php_exe_path);
-
-    // nastavime parametry pro php.exe
     command.arg(script_filename);
-    command.stdin(Stdio::piped());
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
     command.current_dir(Path::new(script_filename).parent().unwrap_or(Path::new(".")));
 
     // nastavime environment variables
@@ -243,109 +250,50 @@ async fn execute_php_exe(
         command.env(key, value);
     }
 
-    // spawnneme process
-    let mut child = command.spawn().map_err(|e| format!("chyba pri spousteni PHP: {}", e))?;
-
-    if !request_body.is_empty() {
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(request_body)
-                .map_err(|e| format!("chyba pri zapisovani do stdin PHP: {}", e))?;
-        }
-    }
-
-    // nastvime timeout pro PHP proces
-    let timeout = Duration::from_secs(timeout_seconds);
-    match child.wait_timeout(timeout) {
-        Ok(status_opt) => {
-            match status_opt {
-                Some(status) => {
-                    if !status.success() {
-                        return Err(format!("chyba pri behu PHP: {}", status));
-                    }
-                }
-                None => {
-                    // Process timeout
-                    child.kill().ok();
-                    return Err("chyba: PHP proces prekrocil timeout".to_string());
-                }
-            }
-        }
-        Err(e) => {
-            return Err(format!("chyba pri cekani na PHP: {}", e));
-        }
-    }
-
-    // sebereme output
-    let mut output = String::new();
-    child.stdout
-        .unwrap()
-        .read_to_string(&mut output)
-        .map_err(|e| format!("chyba pri cteni PHP stdout: {}", e))?;
-
-    // sebereme error output
-    let mut error_output = String::new();
-    child.stderr
-        .unwrap()
-        .read_to_string(&mut error_output)
-        .map_err(|e| format!("chyba pri cteni PHP stderr: {}", e))?;
-
-    if !error_output.is_empty() {
-        println!("chyba pri behu PHP: {}", error_output);
-    }
-
-    Ok(output)
+    php_process::run(command, request_body, timeout, "PHP", stdout).await
 }
 
-fn parse_php_response(response: String) -> HttpResponse {
-    // rozdeleni response na header a body
-    let parts: Vec<&str> = response.split("\r\n\r\n").collect();
-
-    if parts.len() < 2 {
-        // kdyz neni header a body oddeleny
-        return HttpResponse::Ok().content_type("text/html").body(response);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{ test as actix_test, App };
+
+    // php_root_dir se zalohou konfigurace a beznym statickym souborem
+    fn config_manager(dir: &Path) -> Arc<ConfigManager> {
+        let php_root = dir.join("php");
+        fs::create_dir_all(&php_root).unwrap();
+        fs::write(php_root.join("wp-config.php.bak"), "<?php $heslo = 'tajne';").unwrap();
+        fs::write(php_root.join("Backup.BAK"), "tajne").unwrap();
+        fs::write(php_root.join("style.css"), "body {}").unwrap();
+
+        let path = dir.join("server.toml");
+        let path = path.to_str().unwrap();
+        ServerConfig {
+            php_enabled: true,
+            php_root_dir: php_root.to_str().unwrap().to_string(),
+            ..ServerConfig::default()
+        }.save(path).unwrap();
+        Arc::new(ConfigManager::new(path).unwrap())
     }
 
-    let headers_part = parts[0];
-    let body_part = parts[1..].join("\r\n\r\n");
-
-    // zpracovani headeru
-    let mut builder = HttpResponse::Ok();
-    let mut status_code = 200;
-    let mut content_type = "text/html";
+    #[actix_web::test]
+    async fn static_files_from_php_root_respect_deny_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(config_manager(dir.path())))
+                .route("/php/{tail:.*}", web::to(handle_php))
+        ).await;
+        let get = |uri: &str| actix_test::TestRequest::get().uri(uri).to_request();
 
-    for line in headers_part.lines() {
-        if line.is_empty() {
-            continue;
-        }
+        let response = actix_test::call_service(&app, get("/php/style.css")).await;
+        assert_eq!(response.status(), 200);
 
-        // kontrola status kodu
-        if line.starts_with("Status:") {
-            if let Some(status_str) = line.split_whitespace().nth(1) {
-                if let Ok(code) = status_str.parse::<u16>() {
-                    status_code = code;
-                }
-            }
-            continue;
-        }
-
-        // rozdeleni headeru na jmeno a hodnotu
-        if let Some(colon_pos) = line.find(':') {
-            let (name, value) = line.split_at(colon_pos);
-            let name = name.trim();
-            // odstraneni mezery pred hodnotou
-            let value = value[1..].trim();
-
-            if name.eq_ignore_ascii_case("Content-Type") {
-                content_type = value;
-            } else {
-                builder.insert_header((name, value));
-            }
+        for uri in ["/php/wp-config.php.bak", "/php/Backup.BAK"] {
+            let response = actix_test::call_service(&app, get(uri)).await;
+            assert_eq!(response.status(), 404, "{}", uri);
+            let body = actix_test::read_body(response).await;
+            assert!(!String::from_utf8_lossy(&body).contains("tajne"));
         }
     }
-
-    builder.status(StatusCode::from_u16(status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
-    builder.content_type(content_type);
-
-    builder.body(body_part)
 }
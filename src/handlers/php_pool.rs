@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::{ Child, Command, Stdio };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex, Weak };
+use std::time::{ Duration, Instant };
+use tokio::sync::Semaphore;
+use crate::config::ServerConfig;
+use crate::handlers::cgi_response::BodySender;
+use crate::handlers::fastcgi;
+
+const MONITOR_INTERVAL: Duration = Duration::from_secs(1);
+// jak dlouho cekame, nez cerstve spusteny php-cgi zacne poslouchat
+const WORKER_STARTUP_TIMEOUT: Duration = Duration::from_secs(2);
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(20);
+// kolikrat zkusime worker spustit znovu, kdyz se mu nepodari otevrit adresu
+const SPAWN_ATTEMPTS: usize = 3;
+
+lazy_static::lazy_static! {
+    static ref PHP_POOL: Mutex<Option<Arc<PhpCgiPool>>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PoolSettings {
+    php_cgi_path: String,
+    min_workers: usize,
+    max_workers: usize,
+    max_requests: u64,
+    queue_timeout: Duration,
+}
+
+impl PoolSettings {
+    fn from_config(config: &ServerConfig) -> Self {
+        let max_workers = config.php_pool_max.max(1);
+
+        PoolSettings {
+            php_cgi_path: config.php_cgi_path.clone(),
+            min_workers: config.php_pool_min.min(max_workers),
+            max_workers,
+            max_requests: config.php_pool_max_requests,
+            queue_timeout: Duration::from_secs(config.php_pool_queue_timeout),
+        }
+    }
+}
+
+/// pool dlouho bezicich `php-cgi -b` workeru, kazdy zpracovava jeden request naraz;
+/// procesy se spousti a ukoncuji mimo zamek a mimo vlakna actix workeru
+pub struct PhpCgiPool {
+    settings: PoolSettings,
+    workers: Mutex<Workers>,
+    /// pocet volnych mist = max_workers, requesty navic cekaji ve fronte
+    permits: Semaphore,
+    next_id: AtomicU64,
+}
+
+#[derive(Default)]
+struct Workers {
+    list: Vec<Worker>,
+    /// workery, ktere se prave spousti (mimo zamek)
+    starting: usize,
+}
+
+struct Worker {
+    /// adresa pro FastCGI klienta (`unix:/cesta` nebo `127.0.0.1:port`)
+    address: String,
+    child: Child,
+    requests: u64,
+    busy: bool,
+    /// unix socket workeru, po ukonceni se smaze
+    socket: Option<PathBuf>,
+}
+
+// zapujceny worker, po requestu se vraci do poolu pres `checkin`
+struct Lease {
+    address: String,
+}
+
+/// vrati pool pro aktualni konfiguraci; pri zmene nastaveni vytvori novy
+/// (stary pool ukonci sve workery, jakmile dobehnou rozpracovane requesty)
+pub fn get_pool(config: &ServerConfig) -> Arc<PhpCgiPool> {
+    let settings = PoolSettings::from_config(config);
+    let mut current = PHP_POOL.lock().unwrap();
+
+    if let Some(pool) = current.as_ref() {
+        if pool.settings == settings {
+            return pool.clone();
+        }
+    }
+
+    let pool = PhpCgiPool::start(settings);
+    *current = Some(pool.clone());
+    pool
+}
+
+impl PhpCgiPool {
+    fn start(settings: PoolSettings) -> Arc<Self> {
+        let pool = Arc::new(PhpCgiPool {
+            permits: Semaphore::new(settings.max_workers),
+            settings,
+            workers: Mutex::new(Workers::default()),
+            next_id: AtomicU64::new(0),
+        });
+
+        // monitor hlida spadle workery a drzi minimalni pocet bezicich; minimalni
+        // workery spousti hned, ne na vlakne requestu, ktery pool vytvoril
+        let weak: Weak<PhpCgiPool> = Arc::downgrade(&pool);
+        let spawned = std::thread::Builder::new()
+            .name("php-cgi-pool".to_string())
+            .spawn(move || {
+                loop {
+                    match weak.upgrade() {
+                        Some(pool) => pool.ensure_min_workers(),
+                        None => {
+                            break;
+                        }
+                    }
+                    std::thread::sleep(MONITOR_INTERVAL);
+                }
+            });
+
+        if let Err(e) = spawned {
+            println!("chyba pri spusteni monitoru PHP poolu: {}", e);
+        }
+
+        pool
+    }
+
+    /// spusti request na volnem workeru a stdout posila do `stdout`; kdyz jsou
+    /// vsechny obsazene, ceka nejvyse `php_pool_queue_timeout`
+    pub async fn execute(
+        &self,
+        params: &HashMap<String, String>,
+        stdin: &[u8],
+        timeout: Duration,
+        stdout: &mut BodySender
+    ) -> Result<(), String> {
+        let _permit = match tokio::time::timeout(self.settings.queue_timeout, self.permits.acquire()).await {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) => {
+                return Err("PHP pool byl uzavren".to_string());
+            }
+            Err(_) => {
+                return Err("vsechny PHP workery jsou obsazene, vyprsel cas ve fronte".to_string());
+            }
+        };
+
+        let lease = self.checkout().await?;
+
+        let result = tokio::time::timeout(
+            timeout,
+            fastcgi::execute_single(&lease.address, params, stdin, WORKER_STARTUP_TIMEOUT, stdout)
+        ).await;
+
+        match result {
+            Ok(Ok(())) => {
+                self.checkin(lease, true);
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                self.checkin(lease, false);
+                Err(e)
+            }
+            Err(_) => {
+                // script porad bezi, worker ukoncime aby neblokoval dalsi requesty
+                self.checkin(lease, false);
+                Err("chyba: PHP proces prekrocil timeout".to_string())
+            }
+        }
+    }
+
+    async fn checkout(&self) -> Result<Lease, String> {
+        let (lease, dead) = {
+            let mut workers = self.workers.lock().unwrap();
+            let dead = remove_dead(&mut workers.list);
+
+            let lease = workers.list
+                .iter_mut()
+                .find(|w| !w.busy)
+                .map(|worker| {
+                    worker.busy = true;
+                    Lease { address: worker.address.clone() }
+                });
+
+            // semafor zarucuje, ze pod max_workers je vzdy misto pro novy worker
+            if lease.is_none() {
+                workers.starting += 1;
+            }
+            (lease, dead)
+        };
+        dead.into_iter().for_each(retire);
+
+        if let Some(lease) = lease {
+            return Ok(lease);
+        }
+
+        let settings = self.settings.clone();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let spawned = tokio::task
+            ::spawn_blocking(move || spawn_worker(&settings, id)).await
+            .unwrap_or_else(|e| Err(format!("chyba pri spousteni php-cgi workeru: {}", e)));
+
+        let mut workers = self.workers.lock().unwrap();
+        workers.starting -= 1;
+
+        let mut worker = spawned?;
+        worker.busy = true;
+        let lease = Lease { address: worker.address.clone() };
+        workers.list.push(worker);
+
+        Ok(lease)
+    }
+
+    fn checkin(&self, lease: Lease, success: bool) {
+        let retired = {
+            let mut workers = self.workers.lock().unwrap();
+            let total = workers.list.len();
+
+            let index = match workers.list.iter().position(|w| w.address == lease.address) {
+                Some(index) => index,
+                None => {
+                    return;
+                }
+            };
+
+            let worker = &mut workers.list[index];
+            worker.busy = false;
+            worker.requests += 1;
+
+            // po chybe nebo po max_requests (PHP_FCGI_MAX_REQUESTS) worker recyklujeme,
+            // stejne tak workery nad max_workers (soubeh monitoru a requestu)
+            let recycle =
+                !success ||
+                (self.settings.max_requests > 0 &&
+                    worker.requests >= self.settings.max_requests) ||
+                total > self.settings.max_workers;
+
+            recycle.then(|| workers.list.remove(index))
+        };
+
+        if let Some(worker) = retired {
+            retire(worker);
+        }
+    }
+
+    // bezi na vlakne monitoru - spousteni muze blokovat, zamek se drzi jen na evidenci
+    fn ensure_min_workers(&self) {
+        let (missing, dead) = {
+            let mut workers = self.workers.lock().unwrap();
+            let dead = remove_dead(&mut workers.list);
+            let running = workers.list.len() + workers.starting;
+            let missing = self.settings.min_workers.saturating_sub(running);
+            workers.starting += missing;
+            (missing, dead)
+        };
+        dead.into_iter().for_each(retire);
+
+        for attempt in 0..missing {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let spawned = spawn_worker(&self.settings, id);
+
+            let mut workers = self.workers.lock().unwrap();
+            match spawned {
+                Ok(worker) => {
+                    workers.starting -= 1;
+                    workers.list.push(worker);
+                }
+                Err(e) => {
+                    // zbytek zkusime az v dalsim kole monitoru
+                    workers.starting -= missing - attempt;
+                    println!("{}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn spawn_worker(settings: &PoolSettings, id: u64) -> Result<Worker, String> {
+    let mut last_error = String::new();
+
+    // adresu, kterou mezitim obsadil nekdo jiny, php-cgi neotevre a skonci - zkusime jinou
+    for _ in 0..SPAWN_ATTEMPTS {
+        let (address, bind, socket) = worker_address(id)?;
+
+        let child = Command::new(&settings.php_cgi_path)
+            .arg("-b")
+            .arg(&bind)
+            // workery spravujeme sami, php-cgi nema forkovat vlastni deti
+            .env("PHP_FCGI_CHILDREN", "0")
+            .env("PHP_FCGI_MAX_REQUESTS", settings.max_requests.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| {
+                format!("chyba pri spousteni php-cgi workeru {}: {}", settings.php_cgi_path, e)
+            })?;
+
+        let mut worker = Worker {
+            address,
+            child,
+            requests: 0,
+            busy: false,
+            socket,
+        };
+
+        match wait_until_listening(&mut worker) {
+            Ok(()) => {
+                return Ok(worker);
+            }
+            Err(e) => {
+                last_error = e;
+                stop(worker);
+            }
+        }
+    }
+
+    Err(format!("php-cgi worker se nepodarilo spustit: {}", last_error))
+}
+
+
+// na unixu ma kazdy worker vlastni socket v soukromem adresari - zadny soubeh o port;
+// jinde port od systemu, ktery php-cgi otevre az po nasem uvolneni
+#[cfg(unix)]
+fn worker_address(id: u64) -> Result<(String, String, Option<PathBuf>), String> {
+    use std::os::unix::fs::{ DirBuilderExt, MetadataExt };
+
+    let dir = std::env::temp_dir().join(format!("qlira-php-{}", std::process::id()));
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            // adresar po jinem procesu se stejnym PID pouzijeme jen kdyz je nas
+            let metadata = std::fs::symlink_metadata(&dir).map_err(|e| e.to_string())?;
+            let owner = unsafe { libc::geteuid() };
+            if !metadata.is_dir() || metadata.uid() != owner || metadata.mode() & 0o077 != 0 {
+                return Err(format!("adresar pro sockety php-cgi {} neni bezpecny", dir.display()));
+            }
+        }
+        Err(e) => {
+            return Err(format!("chyba pri vytvareni adresare {}: {}", dir.display(), e));
+        }
+    }
+
+    let socket = dir.join(format!("worker-{}.sock", id));
+    std::fs::remove_file(&socket).ok();
+    let path = socket.to_string_lossy().into_owned();
+
+    Ok((format!("unix:{}", path), path, Some(socket)))
+}
+
+#[cfg(not(unix))]
+fn worker_address(_id: u64) -> Result<(String, String, Option<PathBuf>), String> {
+    let port = std::net::TcpListener
+        ::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map_err(|e| format!("chyba pri hledani volneho portu pro php-cgi: {}", e))?
+        .port();
+    let address = format!("127.0.0.1:{}", port);
+
+    Ok((address.clone(), address, None))
+}
+
+// ceka, az worker zacne prijimat spojeni; kdyz mezitim skonci (adresa obsazena), vrati chybu
+fn wait_until_listening(worker: &mut Worker) -> Result<(), String> {
+    let deadline = Instant::now() + WORKER_STARTUP_TIMEOUT;
+
+    loop {
+        if let Ok(Some(status)) = worker.child.try_wait() {
+            return Err(format!("php-cgi {} skoncil pri startu ({})", worker.address, status));
+        }
+        // na TCP mohl port obsadit jiny proces - pripojeni plati, jen kdyz worker stale bezi
+        if accepts_connections(&worker.address) && matches!(worker.child.try_wait(), Ok(None)) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("php-cgi neposloucha na {}", worker.address));
+        }
+        std::thread::sleep(STARTUP_POLL_INTERVAL);
+    }
+}
+
+fn accepts_connections(address: &str) -> bool {
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            return std::os::unix::net::UnixStream::connect(path).is_ok();
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            return false;
+        }
+    }
+
+    match address.parse() {
+        Ok(address) => {
+            std::net::TcpStream::connect_timeout(&address, STARTUP_POLL_INTERVAL).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+// ukonceni procesu blokuje (wait), proto mimo zamek a mimo vlakno actix workeru
+fn retire(worker: Worker) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(move || stop(worker));
+        }
+        Err(_) => stop(worker),
+    }
+}
+
+fn stop(mut worker: Worker) {
+    worker.child.kill().ok();
+    worker.child.wait().ok();
+
+    if let Some(socket) = &worker.socket {
+        std::fs::remove_file(socket).ok();
+    }
+}
+
+// vyjme workery, jejichz proces skoncil (pad nebo vlastni recyklace php-cgi)
+fn remove_dead(workers: &mut Vec<Worker>) -> Vec<Worker> {
+    let mut dead = Vec::new();
+    let mut index = 0;
+
+    while index < workers.len() {
+        match workers[index].child.try_wait() {
+            Ok(None) => {
+                index += 1;
+            }
+            result => {
+                let worker = workers.remove(index);
+                if let Ok(Some(status)) = result {
+                    if !worker.busy {
+                        println!(
+                            "php-cgi worker {} skoncil ({}), bude nahrazen",
+                            worker.address,
+                            status
+                        );
+                    }
+                }
+                dead.push(worker);
+            }
+        }
+    }
+
+    dead
+}
+
+impl Drop for PhpCgiPool {
+    fn drop(&mut self) {
+        let workers = std::mem::take(&mut self.workers.lock().unwrap().list);
+        workers.into_iter().for_each(retire);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn settings(php_cgi_path: &str) -> PoolSettings {
+        PoolSettings {
+            php_cgi_path: php_cgi_path.to_string(),
+            min_workers: 0,
+            max_workers: 2,
+            max_requests: 0,
+            queue_timeout: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn spawn_reports_missing_binary() {
+        let error = spawn_worker(&settings("/neexistuje/php-cgi"), 0).err().unwrap();
+        assert!(error.contains("/neexistuje/php-cgi"), "{}", error);
+    }
+
+    #[test]
+    fn spawn_retries_worker_that_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("php-cgi");
+        std::fs::write(&script, "#!/bin/sh\necho spusten >> \"$0.log\"\nexit 1\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let error = spawn_worker(&settings(script.to_str().unwrap()), 1).err().unwrap();
+
+        assert!(error.contains("skoncil pri startu"), "{}", error);
+        let log = std::fs::read_to_string(dir.path().join("php-cgi.log")).unwrap();
+        assert_eq!(log.lines().count(), SPAWN_ATTEMPTS);
+    }
+
+    #[test]
+    fn worker_sockets_live_in_private_directory() {
+        let (address, bind, socket) = worker_address(7).unwrap();
+        let socket = socket.unwrap();
+
+        assert_eq!(address, format!("unix:{}", bind));
+        assert!(bind.ends_with("worker-7.sock"));
+        let mode = std::fs::metadata(socket.parent().unwrap()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+    }
+}
@@ -0,0 +1,213 @@
+use actix_web::web::Bytes;
+use futures::SinkExt;
+use std::io::ErrorKind;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::process::Command;
+use crate::handlers::cgi_response::BodySender;
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// spusti PHP proces na tokio runtime - stdin se zapisuje soubezne se ctenim
+/// stdout/stderr a stdout jde prubezne do `stdout`, takze worker mezitim obsluhuje
+/// dalsi requesty; pri timeoutu (nebo kdyz klient request zrusi) se ukonci cela
+/// skupina procesu
+pub async fn run(
+    mut command: Command,
+    stdin: &[u8],
+    timeout: Duration,
+    name: &str,
+    stdout: &mut BodySender
+) -> Result<(), String> {
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+    command.kill_on_drop(true);
+
+    // vlastni skupina procesu, at jde ukoncit i to, co si script sam spustil
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = command.spawn().map_err(|e| format!("chyba pri spousteni {}: {}", name, e))?;
+    let mut guard = ProcessTreeGuard { pid: child.id() };
+
+    let mut stdin_pipe = child.stdin.take();
+    let mut stdout_pipe = child.stdout.take();
+    let mut stderr_pipe = child.stderr.take();
+
+    let process = async {
+        let write_stdin = async {
+            if let Some(mut pipe) = stdin_pipe.take() {
+                // script nemusi stdin vubec cist, zavreny pipe neni chyba
+                match pipe.write_all(stdin).await {
+                    Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                        return Err(format!("chyba pri zapisovani do stdin {}: {}", name, e));
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        };
+
+        // vraci, jestli uz klientovi neco odeslo
+        let read_stdout = async {
+            let mut sent = false;
+            if let Some(pipe) = stdout_pipe.as_mut() {
+                let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+                loop {
+                    let read = pipe
+                        .read(&mut buffer).await
+                        .map_err(|e| format!("chyba pri cteni {} stdout: {}", name, e))?;
+                    if read == 0 {
+                        break;
+                    }
+
+                    stdout
+                        .send(Ok(Bytes::copy_from_slice(&buffer[..read]))).await
+                        .map_err(|_| "klient ukoncil spojeni".to_string())?;
+                    sent = true;
+                }
+            }
+            Ok::<_, String>(sent)
+        };
+
+        let read_stderr = async {
+            let mut output = Vec::new();
+            if let Some(pipe) = stderr_pipe.as_mut() {
+                pipe
+                    .read_to_end(&mut output).await
+                    .map_err(|e| format!("chyba pri cteni {} stderr: {}", name, e))?;
+            }
+            Ok::<_, String>(output)
+        };
+
+        let (written, sent, stderr) = tokio::join!(write_stdin, read_stdout, read_stderr);
+        written?;
+        let sent = sent?;
+        let stderr = stderr?;
+
+        let status = child.wait().await.map_err(|e| format!("chyba pri cekani na {}: {}", name, e))?;
+        // uklizeny proces - jeho pid (a tim id skupiny) muze system pridelit nekomu jinemu,
+        // pripadne procesy na pozadi proto nechavame byt
+        guard.pid = None;
+
+        if !stderr.is_empty() {
+            println!("chyba pri behu {}: {}", name, String::from_utf8_lossy(&stderr));
+        }
+
+        // kdyz uz odpoved odesla (napr. chybova stranka PHP), jen to zalogujeme
+        if !status.success() {
+            if !sent {
+                return Err(format!("chyba pri behu {}: {}", name, status));
+            }
+            println!("{} skoncil s chybou: {}", name, status);
+        }
+
+        Ok(())
+    };
+
+    match tokio::time::timeout(timeout, process).await {
+        Ok(result) => result,
+        Err(_) => {
+            // skupina se ukonci jeste pred uklizenim procesu, dokud jeji id patri nam
+            guard.kill();
+            child.wait().await.ok();
+            Err(format!("chyba: {} proces prekrocil timeout", name))
+        }
+    }
+}
+
+// pri zahozeni ukonci celou skupinu procesu (timeout nebo zruseny request); drzi pid
+// jen do uklizeni procesu, potom uz by signal mohl dostat cizi skupina
+struct ProcessTreeGuard {
+    pid: Option<u32>,
+}
+
+impl ProcessTreeGuard {
+    fn kill(&mut self) {
+        let pid = match self.pid.take() {
+            Some(pid) => pid,
+            None => {
+                return;
+            }
+        };
+
+        #[cfg(unix)]
+        unsafe {
+            // process_group(0) => id skupiny je pid spusteneho procesu
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+
+        #[cfg(windows)]
+        {
+            std::process::Command::new("taskkill")
+                .args(["/T", "/F", "/PID", &pid.to_string()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .ok();
+        }
+    }
+}
+
+impl Drop for ProcessTreeGuard {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc;
+    use futures::StreamExt;
+    use std::time::Instant;
+
+    // zombie (ukonceny, jen jeste neuklizeny) uz nebezi
+    fn alive(pid: libc::pid_t) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.contains(") Z "),
+            Err(_) => unsafe { libc::kill(pid, 0) == 0 },
+        }
+    }
+
+    #[actix_web::test]
+    async fn timeout_kills_whole_process_group() {
+        // script vypise pid procesu na pozadi a ceka - timeout musi ukoncit oba
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & echo $!; wait"]);
+        let (mut sender, mut receiver) = mpsc::channel(4);
+
+        let started = Instant::now();
+        let result = run(command, b"", Duration::from_millis(300), "sh", &mut sender).await;
+
+        assert!(result.unwrap_err().contains("timeout"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output = receiver.next().await.unwrap().unwrap();
+        let background: libc::pid_t = String::from_utf8_lossy(&output).trim().parse().unwrap();
+        // osiroceny sleep uklidi init, chvili to muze trvat
+        for _ in 0..100 {
+            if !alive(background) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!alive(background));
+    }
+
+    #[actix_web::test]
+    async fn finished_script_passes_output() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "cat; echo konec >&2"]);
+        let (mut sender, receiver) = mpsc::channel(4);
+
+        let result = run(command, b"vstup", Duration::from_secs(5), "sh", &mut sender).await;
+        drop(sender);
+
+        assert_eq!(result, Ok(()));
+        let output: Vec<_> = receiver.map(|chunk| chunk.unwrap()).collect().await;
+        assert_eq!(output.concat(), b"vstup");
+    }
+}
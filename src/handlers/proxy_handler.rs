@@ -0,0 +1,459 @@
+use actix_web::{ web, HttpRequest, HttpResponse };
+use actix_web::http::{ header, StatusCode };
+use futures::channel::mpsc;
+use futures::{ SinkExt, StreamExt };
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::config::{ ConfigManager, LocationHandler, MatchedLocation, ProxyRule };
+use crate::server::upstream::UpstreamRegistry;
+
+// hlavicky platne jen pro jedno spojeni, ty se dal nepredavaji
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// HTTP klient pro upstreamy - vytvari se pro kazdy worker zvlast
+pub fn proxy_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        // redirecty upstreamu posilame klientovi, nesledujeme je
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap_or_default()
+}
+
+pub async fn handle_proxy(
+    req: HttpRequest,
+    payload: web::Payload,
+    config_manager: web::Data<Arc<ConfigManager>>,
+    upstreams: web::Data<Arc<UpstreamRegistry>>,
+    client: web::Data<reqwest::Client>
+) -> HttpResponse {
+    let config = config_manager.get_config();
+
+    // proxy location (i z `[[proxy]]`) uz nese pravidlo, jinak ho hledame v konfiguraci
+    let rule = match MatchedLocation::of(&req) {
+        Some(matched) if matched.location.handler == LocationHandler::Proxy => {
+            matched.location.proxy_rule(&matched.url_prefix)
+        }
+        _ =>
+            match config.find_proxy(req.path()) {
+                Some(rule) => rule.clone(),
+                None => {
+                    return HttpResponse::NotFound().body(
+                        "proxy pro tuto cestu neni nakonfigurovana"
+                    );
+                }
+            }
+    };
+
+    let timeout = Duration::from_secs(rule.timeout);
+
+    // primo zadana URL - bez load balancingu
+    if rule.upstream.contains("://") {
+        let url = upstream_url(&rule, &rule.upstream, &req);
+        return forward(&client, &req, payload, &url, timeout, &config.trusted_proxies).await
+            .unwrap_or_else(|status|
+                HttpResponse::build(status).body("chyba pri komunikaci s upstream serverem")
+            );
+    }
+
+    let client_ip = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+
+    let peer = match upstreams.select(&rule.upstream, &client_ip) {
+        Some(peer) => peer,
+        None => {
+            println!("upstream skupina {} neexistuje nebo nema servery", rule.upstream);
+            return HttpResponse::BadGateway().body("upstream skupina neni dostupna");
+        }
+    };
+
+    let url = upstream_url(&rule, peer.url(), &req);
+
+    match forward(&client, &req, payload, &url, timeout, &config.trusted_proxies).await {
+        Ok(response) => {
+            peer.report_status(response.status().as_u16());
+            response
+        }
+        Err(status) => {
+            peer.report_failure();
+            HttpResponse::build(status).body("chyba pri komunikaci s upstream serverem")
+        }
+    }
+}
+
+// sestavi URL na upstreamu z puvodni cesty a query stringu
+pub fn upstream_url(rule: &ProxyRule, upstream: &str, req: &HttpRequest) -> String {
+    let path = req.path();
+
+    let path = if rule.strip_prefix {
+        let rest = path.strip_prefix(rule.prefix.as_str()).unwrap_or(path);
+        if rest.starts_with('/') { rest.to_string() } else { format!("/{}", rest) }
+    } else {
+        path.to_string()
+    };
+
+    let mut url = format!("{}{}", upstream.trim_end_matches('/'), path);
+
+    if !req.query_string().is_empty() {
+        url.push('?');
+        url.push_str(req.query_string());
+    }
+
+    url
+}
+
+/// preposle request na `url` a odpoved streamuje zpet klientovi;
+/// pri chybe spojeni vraci 502, pri timeoutu 504. `X-Forwarded-*` od klienta
+/// se prevezmou jen od adres z `trusted_proxies`, jinak se nastavi znovu
+pub async fn forward(
+    client: &reqwest::Client,
+    req: &HttpRequest,
+    mut payload: web::Payload,
+    url: &str,
+    timeout: Duration,
+    trusted_proxies: &[String]
+) -> Result<HttpResponse, StatusCode> {
+    let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())
+        .map_err(|_| StatusCode::METHOD_NOT_ALLOWED)?;
+
+    let mut builder = client.request(method, url).timeout(timeout);
+
+    for (name, value) in req.headers() {
+        let name = name.as_str();
+        if is_hop_by_hop(name) || name == "host" || name.starts_with("x-forwarded-") {
+            continue;
+        }
+        builder = builder.header(name, value.as_bytes());
+    }
+
+    // X-Forwarded-* hlavicky
+    let peer_ip = req.peer_addr().map(|addr| addr.ip());
+    let client_ip = peer_ip.map(|ip| ip.to_string()).unwrap_or_default();
+    let trusted = peer_ip.is_some_and(|ip| is_trusted_proxy(ip, trusted_proxies));
+
+    let previous = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| trusted);
+    let forwarded_for = match previous {
+        Some(previous) if !client_ip.is_empty() => format!("{}, {}", previous, client_ip),
+        Some(previous) => previous.to_string(),
+        None => client_ip,
+    };
+
+    if !forwarded_for.is_empty() {
+        builder = builder.header("x-forwarded-for", forwarded_for);
+    }
+
+    // ConnectionInfo cte i X-Forwarded-Proto/Host, takze ho pouzijeme jen za duveryhodnou proxy
+    let (scheme, host) = if trusted {
+        let conn_info = req.connection_info().clone();
+        (conn_info.scheme().to_string(), conn_info.host().to_string())
+    } else {
+        let scheme = if req.app_config().secure() { "https" } else { "http" };
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| req.uri().authority().map(|a| a.as_str()))
+            .unwrap_or_else(|| req.app_config().host());
+        (scheme.to_string(), host.to_string())
+    };
+    builder = builder.header("x-forwarded-proto", scheme).header("x-forwarded-host", host);
+
+    // telo posilame jen kdyz ho klient opravdu poslal (GET bez tela nesmi jit chunked)
+    let has_body =
+        req.headers().contains_key(header::TRANSFER_ENCODING) ||
+        req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|len| len.trim() != "0");
+
+    if has_body {
+        // actix payload neni Send, proto body posilame do reqwestu pres kanal
+        let (mut tx, rx) = mpsc::channel::<Result<web::Bytes, std::io::Error>>(8);
+        actix_web::rt::spawn(async move {
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk.map_err(|e| std::io::Error::other(e.to_string()));
+                let failed = chunk.is_err();
+                if tx.send(chunk).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        builder = builder.body(reqwest::Body::wrap_stream(rx));
+    }
+
+    let upstream_response = match builder.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            println!("chyba proxy {} {}: {}", req.method(), url, e);
+            return Err(if e.is_timeout() { StatusCode::GATEWAY_TIMEOUT } else { StatusCode::BAD_GATEWAY });
+        }
+    };
+
+    let status = StatusCode::from_u16(upstream_response.status().as_u16()).unwrap_or(
+        StatusCode::BAD_GATEWAY
+    );
+
+    let mut response = HttpResponse::build(status);
+
+    for (name, value) in upstream_response.headers() {
+        let name = name.as_str();
+        if is_hop_by_hop(name) || name == "content-length" {
+            continue;
+        }
+        response.append_header((name, value.as_bytes()));
+    }
+
+    // se znamou delkou posleme Content-Length, jinak chunked; pri kompresi delku
+    // zahodi middleware Compress a posila chunked
+    if let Some(length) = upstream_response.content_length() {
+        response.no_chunking(length);
+    }
+
+    Ok(response.streaming(upstream_response.bytes_stream()))
+}
+
+fn is_hop_by_hop(name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h))
+}
+
+// adresa `127.0.0.1` nebo sit `10.0.0.0/8`; neplatne polozky se ignoruji
+fn is_trusted_proxy(ip: IpAddr, trusted_proxies: &[String]) -> bool {
+    let ip = ip.to_canonical();
+
+    trusted_proxies.iter().any(|entry| {
+        let (address, prefix) = match entry.split_once('/') {
+            Some((address, prefix)) => (address, prefix.parse::<u32>().ok()),
+            None => (entry.as_str(), None),
+        };
+        let network = match address.trim().parse::<IpAddr>() {
+            Ok(network) => network,
+            Err(_) => {
+                return false;
+            }
+        };
+
+        match (network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(u32::from(network).into(), u32::from(ip).into(), prefix, 32)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(network), u128::from(ip), prefix, 128)
+            }
+            _ => false,
+        }
+    })
+}
+
+fn prefix_matches(network: u128, ip: u128, prefix: Option<u32>, bits: u32) -> bool {
+    let prefix = prefix.unwrap_or(bits);
+    if prefix > bits {
+        return false;
+    }
+    // pocet bitu, ktere se neporovnavaji
+    let host_bits = bits - prefix;
+    host_bits == bits || network >> host_bits == ip >> host_bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::middleware::compress::Compress;
+    use actix_web::{ test as actix_test, App, HttpServer };
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use std::net::SocketAddr;
+
+    // nahradni backend - vrati v hlavickach, co od proxy dostal, a telo posle zpet
+    async fn echo(req: HttpRequest, body: web::Bytes) -> HttpResponse {
+        let mut response = HttpResponse::Created();
+        response
+            .insert_header(("x-echo-method", req.method().as_str()))
+            .insert_header(("x-echo-query", req.query_string()))
+            .insert_header(("x-upstream", "echo"));
+
+        if let Some(content_type) = req.headers().get(header::CONTENT_TYPE) {
+            response.insert_header((header::CONTENT_TYPE, content_type.clone()));
+        }
+
+        for name in ["x-custom", "x-forwarded-for", "x-forwarded-proto", "x-forwarded-host"] {
+            if let Some(value) = req.headers().get(name) {
+                response.insert_header((format!("x-echo-{}", name), value.clone()));
+            }
+        }
+
+        response.body(body)
+    }
+
+    fn start_backend() -> SocketAddr {
+        let server = HttpServer::new(|| App::new().default_service(web::to(echo)))
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        address
+    }
+
+    async fn proxy_request(
+        backend: SocketAddr,
+        trusted_proxies: Vec<String>,
+        request: actix_test::TestRequest
+    ) -> actix_web::dev::ServiceResponse {
+        // vychozi konfigurace pro Compress - bez Accept-Encoding odpoved nemeni
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("server.toml");
+        let config_path = config_path.to_str().unwrap();
+        ServerConfig::default().save(config_path).unwrap();
+        let config_manager = Arc::new(ConfigManager::new(config_path).unwrap());
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(proxy_client()))
+                .app_data(web::Data::new(config_manager))
+                .wrap(Compress)
+                .default_service(
+                    web::to(
+                        move |
+                            req: HttpRequest,
+                            payload: web::Payload,
+                            client: web::Data<reqwest::Client>
+                        | {
+                            let trusted_proxies = trusted_proxies.clone();
+                            async move {
+                                let url = format!("http://{}{}", backend, req.uri());
+                                let timeout = Duration::from_secs(5);
+                                forward(&client, &req, payload, &url, timeout, &trusted_proxies)
+                                    .await
+                                    .unwrap_or_else(|status| HttpResponse::build(status).finish())
+                            }
+                        }
+                    )
+                )
+        ).await;
+
+        actix_test::call_service(&app, request.to_request()).await.map_into_boxed_body()
+    }
+
+    fn header<'a>(response: &'a actix_web::dev::ServiceResponse, name: &str) -> &'a str {
+        response.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("")
+    }
+
+    #[actix_web::test]
+    async fn forward_keeps_request_and_passes_response_back() {
+        let backend = start_backend();
+        let request = actix_test::TestRequest::put()
+            .uri("/api/items?id=7&sort=asc")
+            .insert_header((header::HOST, "shop.example.com"))
+            .insert_header(("x-custom", "zachovat"))
+            .insert_header(("x-forwarded-for", "6.6.6.6"))
+            .peer_addr("203.0.113.9:40000".parse().unwrap())
+            .set_payload("telo requestu");
+
+        let response = proxy_request(backend, Vec::new(), request).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(header(&response, "x-upstream"), "echo");
+        assert_eq!(header(&response, "x-echo-method"), "PUT");
+        assert_eq!(header(&response, "x-echo-query"), "id=7&sort=asc");
+        assert_eq!(header(&response, "x-echo-x-custom"), "zachovat");
+        // klient neni duveryhodna proxy, jeho X-Forwarded-For se zahodi
+        assert_eq!(header(&response, "x-echo-x-forwarded-for"), "203.0.113.9");
+        assert_eq!(header(&response, "x-echo-x-forwarded-proto"), "http");
+        assert_eq!(header(&response, "x-echo-x-forwarded-host"), "shop.example.com");
+
+        let body = actix_test::read_body(response).await;
+        assert_eq!(body, "telo requestu");
+    }
+
+    #[actix_web::test]
+    async fn upstream_length_is_dropped_when_compressed() {
+        let backend = start_backend();
+        let text = "Prilis zlutoucky kun upel dabelske ody. ".repeat(100);
+        let request = |accept_encoding: &str| {
+            actix_test::TestRequest::post()
+                .uri("/")
+                .insert_header((header::CONTENT_TYPE, "text/plain"))
+                .insert_header((header::ACCEPT_ENCODING, accept_encoding))
+                .set_payload(text.clone())
+        };
+
+        // delka upstreamu plati jen pro nekomprimovane telo
+        let response = proxy_request(backend, Vec::new(), request("identity")).await;
+        assert!(!response.response().head().chunked());
+        assert_eq!(actix_test::read_body(response).await, text);
+
+        let response = proxy_request(backend, Vec::new(), request("gzip")).await;
+        assert_eq!(header(&response, "content-encoding"), "gzip");
+        assert!(response.response().head().chunked());
+        let body = actix_test::read_body(response).await;
+        let mut decoded = String::new();
+        GzDecoder::new(&body[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text);
+    }
+
+    #[actix_web::test]
+    async fn forward_appends_to_trusted_proxy_headers() {
+        let backend = start_backend();
+        let request = actix_test::TestRequest::get()
+            .uri("/")
+            .insert_header((header::HOST, "internal:8080"))
+            .insert_header(("x-forwarded-for", "198.51.100.4"))
+            .insert_header(("x-forwarded-proto", "https"))
+            .insert_header(("x-forwarded-host", "shop.example.com"))
+            .peer_addr("10.1.2.3:40000".parse().unwrap());
+
+        let response = proxy_request(backend, vec!["10.0.0.0/8".to_string()], request).await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(header(&response, "x-echo-x-forwarded-for"), "198.51.100.4, 10.1.2.3");
+        assert_eq!(header(&response, "x-echo-x-forwarded-proto"), "https");
+        assert_eq!(header(&response, "x-echo-x-forwarded-host"), "shop.example.com");
+    }
+
+    #[actix_web::test]
+    async fn forward_reports_unreachable_backend() {
+        // port po zavrenem listeneru - spojeni skonci odmitnutim
+        let backend = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let request = actix_test::TestRequest::get().uri("/");
+
+        let response = proxy_request(backend, Vec::new(), request).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn trusted_proxy_matches_address_and_network() {
+        let trusted: Vec<String> = ["127.0.0.1", "10.0.0.0/8", "fd00::/8"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert!(is_trusted_proxy(ip("127.0.0.1"), &trusted));
+        assert!(is_trusted_proxy(ip("::ffff:127.0.0.1"), &trusted));
+        assert!(is_trusted_proxy(ip("10.200.0.1"), &trusted));
+        assert!(is_trusted_proxy(ip("fd12::1"), &trusted));
+        assert!(!is_trusted_proxy(ip("127.0.0.2"), &trusted));
+        assert!(!is_trusted_proxy(ip("11.0.0.1"), &trusted));
+        assert!(!is_trusted_proxy(ip("fe80::1"), &trusted));
+        assert!(!is_trusted_proxy(ip("10.0.0.1"), &[]));
+    }
+}
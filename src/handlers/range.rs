@@ -0,0 +1,215 @@
+use actix_web::web::Bytes;
+use futures::{ future, stream, Stream, StreamExt };
+use std::fs::File;
+use std::io::{ self, Seek, SeekFrom };
+use std::path::Path;
+use tokio::io::{ AsyncReadExt, Take };
+use tokio_util::io::ReaderStream;
+
+// vic rozsahu po slouceni uz neni rozumny request, posleme cely soubor
+const MAX_RANGES: usize = 32;
+
+/// velikost bloku pri streamovani souboru
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// rozsah bajtu vcetne `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// hodnota hlavicky Content-Range
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// bez Range nebo s hlavickou, kterou ignorujeme - posle se cely soubor
+    Full,
+    Partial(Vec<ByteRange>),
+    /// zadny rozsah nelezi v souboru - 416
+    Unsatisfiable,
+}
+
+/// rozparsuje hlavicku Range (RFC 9110, 14.2); syntakticky neplatna hlavicka
+/// nebo jina jednotka nez `bytes` se ignoruje
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let specs = match header.split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => {
+            return RangeRequest::Full;
+        }
+    };
+
+    let mut ranges = Vec::new();
+    let mut any_spec = false;
+
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        any_spec = true;
+
+        let (first, last) = match spec.split_once('-') {
+            Some(parts) => parts,
+            None => {
+                return RangeRequest::Full;
+            }
+        };
+
+        let range = if first.is_empty() {
+            // "-500" = poslednich 500 bajtu
+            let suffix = match last.parse::<u64>() {
+                Ok(suffix) => suffix,
+                Err(_) => {
+                    return RangeRequest::Full;
+                }
+            };
+            if suffix == 0 || size == 0 {
+                continue;
+            }
+            ByteRange { start: size.saturating_sub(suffix), end: size - 1 }
+        } else {
+            let start = match first.parse::<u64>() {
+                Ok(start) => start,
+                Err(_) => {
+                    return RangeRequest::Full;
+                }
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => {
+                        return RangeRequest::Full;
+                    }
+                }
+            };
+            if start >= size {
+                continue;
+            }
+            ByteRange { start, end: end.min(size - 1) }
+        };
+
+        ranges.push(range);
+    }
+
+    if !any_spec {
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    // prekryvajici se a navazujici rozsahy sloucime (RFC dovoluje)
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+
+    if merged.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(merged)
+}
+
+/// If-Range plati, jen kdyz presne odpovida silnemu ETagu nebo Last-Modified;
+/// jinak se posle cely soubor
+pub fn if_range_matches(if_range: &str, etag: &str, last_modified: &str) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        // slaby ETag se pro If-Range nikdy neshoduje
+        !etag.is_empty() && !etag.starts_with("W/") && if_range == etag
+    } else {
+        !last_modified.is_empty() && if_range == last_modified
+    }
+}
+
+/// otevre soubor posunuty na zacatek rozsahu, cteni skonci na jeho konci
+pub fn open_range(path: &Path, range: ByteRange) -> Result<Take<tokio::fs::File>, io::Error> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    Ok(tokio::fs::File::from_std(file).take(range.length()))
+}
+
+/// telo multipart/byteranges pro vice rozsahu jako stream, vcetne celkove delky
+pub fn multipart_stream(
+    path: &Path,
+    ranges: &[ByteRange],
+    size: u64,
+    content_type: &str,
+    boundary: &str
+) -> Result<(u64, impl Stream<Item = Result<Bytes, io::Error>>), io::Error> {
+    let mut parts = Vec::with_capacity(ranges.len());
+    let mut length = 0;
+
+    for (index, range) in ranges.iter().enumerate() {
+        let head = format!(
+            "{}--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            if index == 0 { "" } else { "\r\n" },
+            boundary,
+            content_type,
+            range.content_range(size)
+        );
+        length += (head.len() as u64) + range.length();
+
+        let body = ReaderStream::with_capacity(open_range(path, *range)?, CHUNK_SIZE);
+        parts.push(stream::once(future::ready(Ok(Bytes::from(head)))).chain(body));
+    }
+
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    length += tail.len() as u64;
+
+    let body = stream::iter(parts)
+        .flatten()
+        .chain(stream::once(future::ready(Ok(Bytes::from(tail)))));
+
+    Ok((length, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAST_MODIFIED: &str = "Tue, 01 Sep 2026 10:00:00 GMT";
+
+    #[test]
+    fn if_range_needs_strong_etag_or_exact_date() {
+        assert!(if_range_matches("\"1f-abc\"", "\"1f-abc\"", LAST_MODIFIED));
+        assert!(!if_range_matches("\"1f-abd\"", "\"1f-abc\"", LAST_MODIFIED));
+        // slabe porovnani pro If-Range RFC 9110 nepovoluje
+        assert!(!if_range_matches("W/\"1f-abc\"", "W/\"1f-abc\"", LAST_MODIFIED));
+        assert!(!if_range_matches("\"1f-abc\"", "", LAST_MODIFIED));
+        assert!(if_range_matches(LAST_MODIFIED, "\"1f-abc\"", LAST_MODIFIED));
+        assert!(!if_range_matches("Wed, 02 Sep 2026 10:00:00 GMT", "", LAST_MODIFIED));
+    }
+
+    #[test]
+    fn parses_single_suffix_and_merged_ranges() {
+        let range = |start, end| ByteRange { start, end };
+
+        assert_eq!(parse_range("bytes=0-9", 100), RangeRequest::Partial(vec![range(0, 9)]));
+        assert_eq!(parse_range("bytes=90-", 100), RangeRequest::Partial(vec![range(90, 99)]));
+        assert_eq!(parse_range("bytes=-10", 100), RangeRequest::Partial(vec![range(90, 99)]));
+        assert_eq!(
+            parse_range("bytes=0-9,5-19", 100),
+            RangeRequest::Partial(vec![range(0, 19)])
+        );
+        assert_eq!(parse_range("bytes=200-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("items=0-9", 100), RangeRequest::Full);
+    }
+}
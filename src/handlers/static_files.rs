@@ -1,21 +1,47 @@
-use actix_web::{ web, HttpRequest, HttpResponse, Responder };
+use actix_web::{ web, web::Bytes, HttpRequest, HttpResponse };
+use actix_web::body::{ MessageBody, SizedStream };
 use std::fs;
 use std::io;
 use std::path::{ Path, PathBuf };
-use std::time::{ UNIX_EPOCH };
+use std::time::{ SystemTime, UNIX_EPOCH };
 use mime_guess::from_path;
-use std::io::Read;
-use std::sync::Arc;
-use actix_web::http::header;
-use ring::digest::{ Context, SHA256 };
-use brotli::CompressorReader;
-use flate2::read::{ GzEncoder, DeflateEncoder };
-use flate2::Compression;
-use std::collections::HashMap;
-use crate::config::ConfigManager;
+use std::io::{ BufWriter, Write };
+use std::sync::{ Arc, Mutex };
+use actix_web::http::{ header, Method };
+use futures::{ stream, Stream };
+use std::collections::{ HashMap, HashSet };
+use rand::Rng;
+use tokio::sync::mpsc;
+use tokio_util::io::ReaderStream;
+use crate::config::{ ConfigManager, MatchedLocation, ServerConfig };
+use crate::server::cache::{ CacheKey, CachedResponse, ResponseCache };
+use crate::utils::compression::{
+    compress,
+    is_compressible,
+    parse_weight,
+    ranked,
+    CompressionLevels,
+    Encoding,
+};
+use crate::handlers::access::{ check_access, log_violation };
+use crate::utils::path_resolver::resolve;
+use crate::handlers::autoindex::directory_listing;
+use crate::handlers::cgi_env::{ directory_index, is_php };
+use crate::handlers::etag::{ generate_etag, none_match, weak };
+use crate::handlers::range::{
+    if_range_matches,
+    multipart_stream,
+    open_range,
+    parse_range,
+    RangeRequest,
+    CHUNK_SIZE,
+};
 
 // mapovani pripony souboru na dobu trvani cache (v sekundach)
 lazy_static::lazy_static! {
+    // zastarale predkomprimovane soubory (sidecar, zmena zdroje), ktere uz byly zalogovane
+    static ref STALE_SIDECARS: Mutex<HashSet<(PathBuf, SystemTime)>> = Mutex::new(HashSet::new());
+
     static ref CACHE_POLICY: HashMap<&'static str, u32> = {
         let mut m = HashMap::new();
         // casto menici se
@@ -47,216 +73,664 @@ lazy_static::lazy_static! {
 pub async fn serve_static_file(
     req: HttpRequest,
     config_manager: web::Data<Arc<ConfigManager>>
-) -> impl Responder {
-    let config = config_manager.get_config();
+) -> HttpResponse {
+    let config = config_manager.get_request_config(&req);
 
-    let filename = req.match_info().get("filename").unwrap_or("");
+    // cesta v koreni location, mimo tabulku location cela cesta za /static/
+    let filename = match MatchedLocation::of(&req) {
+        Some(matched) => matched.relative_path,
+        None => req.path().strip_prefix("/static/").unwrap_or("").to_string(),
+    };
 
-    // normalizovani cesty, abychom preventnuli directory traversal a ziskali spravnou cestu
-    let path = match normalize_path(filename) {
-        Ok(p) => p,
-        Err(_) => {
+    // dekodovani a kontrola cesty proti directory traversal
+    let static_root = PathBuf::from(&config.static_root);
+    let (path, full_path) = match resolve(&static_root, &filename) {
+        Ok(resolved) => (resolved.relative, resolved.full),
+        Err(e) => {
+            log_violation(&req, &static_root.join(&filename), &e);
             return HttpResponse::BadRequest().body("neplatna cesta");
         }
     };
 
-    let full_path = PathBuf::from(&config.static_root).join(&path);
+    // skryte a zakazane soubory a symlinky mimo koren se tvari jako neexistujici
+    if let Err(denied) = check_access(&static_root, &path, &config) {
+        log_violation(&req, &full_path, &denied);
+        return HttpResponse::NotFound().body(format!("soubor nenalezen: {}", path));
+    }
+
+    if full_path.is_dir() {
+        // PHP index by se ze statickeho korene poslal jako zdrojak
+        let index_files: Vec<String> = config.index_files
+            .iter()
+            .filter(|name| !is_php(Path::new(name)))
+            .cloned()
+            .collect();
+        let index = directory_index(&full_path, &index_files);
+
+        if index.is_some() || config.autoindex {
+            if let Some(response) = redirect_to_directory(&req) {
+                return response;
+            }
+        }
+
+        if let Some(index) = index {
+            let index_path = format!("{}/{}", path, index);
+            if let Err(denied) = check_access(&static_root, &index_path, &config) {
+                log_violation(&req, &full_path.join(index), &denied);
+                return HttpResponse::NotFound().body(format!("soubor nenalezen: {}", path));
+            }
+            return serve_file(&req, &config, &static_root, &full_path.join(index));
+        }
+        if config.autoindex {
+            return directory_listing(&req, &config, &static_root, &path);
+        }
+    }
 
     if !full_path.exists() || !full_path.is_file() {
+        if let Some(response) = spa_fallback(&req, &config, &static_root, &path) {
+            return response;
+        }
         return HttpResponse::NotFound().body(format!("soubor nenalezen: {}", path));
     }
 
+    serve_file(&req, &config, &static_root, &full_path)
+}
+
+// single-page aplikace: HTML navigace (cesta bez pripony nebo .html) na neexistujici
+// soubor dostane dokument aplikace s 200, chybejici soubory s jinou priponou (.js,
+// .map, .wasm, ...) dal konci 404; chybejici dokument hlasi uz nacteni konfigurace
+fn spa_fallback(
+    req: &HttpRequest,
+    config: &ServerConfig,
+    static_root: &Path,
+    path: &str
+) -> Option<HttpResponse> {
+    let document = config.spa_fallback.as_deref()?;
+
+    let accept = req.headers().get(header::ACCEPT).and_then(|value| value.to_str().ok());
+    if !accepts_html(accept) {
+        return None;
+    }
+
+    let is_navigation = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        None => true,
+        Some(ext) => ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"),
+    };
+    if !is_navigation {
+        return None;
+    }
+
+    let document = match resolve(static_root, document) {
+        Ok(document) if document.full.is_file() => document,
+        _ => {
+            return None;
+        }
+    };
+    if let Err(denied) = check_access(static_root, &document.relative, config) {
+        log_violation(req, &document.full, &denied);
+        return None;
+    }
+
+    // stejna URL dava podle Accept dokument aplikace nebo 404
+    let mut response = serve_file(req, config, static_root, &document.full);
+    response.headers_mut().append(header::VARY, header::HeaderValue::from_static("Accept"));
+    Some(response)
+}
+
+// prijima klient text/html? Vahu urcuje nejpresnejsi odpovidajici rozsah
+// (`text/html` > `text/*` > `*/*`, RFC 9110, 12.5.1), `q=0` HTML vylucuje
+fn accepts_html(accept: Option<&str>) -> bool {
+    let accept = match accept {
+        Some(accept) => accept,
+        None => {
+            return false;
+        }
+    };
+
+    let mut best: Option<(u8, f32)> = None;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let specificity = match parts.next().unwrap_or("").trim().to_ascii_lowercase().as_str() {
+            "text/html" => 3,
+            "text/*" => 2,
+            "*/*" => 1,
+            _ => {
+                continue;
+            }
+        };
+
+        let mut weight = Some(1.0);
+        for param in parts {
+            if let Some((name, value)) = param.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("q") {
+                    weight = parse_weight(value.trim());
+                }
+            }
+        }
+
+        // polozku s neplatnou vahou ignorujeme
+        if let Some(weight) = weight {
+            if best.is_none_or(|(best, _)| specificity > best) {
+                best = Some((specificity, weight));
+            }
+        }
+    }
+
+    best.is_some_and(|(_, weight)| weight > 0.0)
+}
+
+/// 301 na URL adresare s lomitkem na konci, aby relativni odkazy v indexu
+/// vedly do adresare; None, kdyz lomitko uz ma
+pub fn redirect_to_directory(req: &HttpRequest) -> Option<HttpResponse> {
+    if req.path().ends_with('/') {
+        return None;
+    }
+
+    let location = match req.query_string() {
+        "" => format!("{}/", req.path()),
+        query => format!("{}/?{}", req.path(), query),
+    };
+
+    Some(HttpResponse::MovedPermanently().insert_header((header::LOCATION, location)).finish())
+}
+
+/// odesle existujici soubor vcetne cache hlavicek, podminenych requestu a komprese;
+/// telo se streamuje z disku po castech. `root` je koren, ze ktereho soubor pochazi -
+/// podle nej se kontroluji i predkomprimovane varianty
+pub fn serve_file(
+    req: &HttpRequest,
+    config: &ServerConfig,
+    root: &Path,
+    full_path: &Path
+) -> HttpResponse {
+    let path = full_path.to_string_lossy().into_owned();
+
     // ziskame metadata
-    let metadata = match fs::metadata(&full_path) {
+    let metadata = match fs::metadata(full_path) {
         Ok(m) => m,
         Err(_) => {
             return HttpResponse::InternalServerError().body("chyba pri ziskavani metadat souboru");
         }
     };
+    let size = metadata.len();
 
-    // jestli je velikost souboru vetsi nez max_file_size, vracime chybu
-    if (metadata.len() as usize) > config.max_file_size {
-        return HttpResponse::PayloadTooLarge().body("soubor je prilis velky");
+    // limit velikosti je volitelny - soubor se do pameti nenacita
+    if let Some(max_file_size) = config.max_file_size {
+        if size > (max_file_size as u64) {
+            return HttpResponse::PayloadTooLarge().body("soubor je prilis velky");
+        }
     }
 
     // pokud je development mode, vracime soubor bez cachovani
     if config.development_mode {
-        match fs::read(&full_path) {
-            Ok(content) => {
+        let content_type = get_content_type(&path);
+        let cache_control = "no-store, max-age=0";
+
+        // bez validatoru - If-Range se nikdy neshoduje a posle se cely soubor
+        let range = range_response(req, full_path, &content_type, size, "", "", cache_control);
+        if let Some(response) = range {
+            return response;
+        }
+
+        match file_body(full_path, size) {
+            Ok(body) => {
                 HttpResponse::Ok()
-                    .content_type(get_content_type(&path))
-                    .insert_header((header::CACHE_CONTROL, "no-store, max-age=0"))
-                    .body(content)
+                    .content_type(content_type)
+                    .insert_header((header::CACHE_CONTROL, cache_control))
+                    .insert_header((header::ACCEPT_RANGES, "bytes"))
+                    .body(body)
             }
             Err(_) => { HttpResponse::InternalServerError().body("chyba pri cteni souboru") }
         }
     } else {
-        // vygenerujeme ETag pro soubor
-        let etag = match generate_etag(&full_path) {
-            Ok(tag) => tag,
-            Err(_) => String::new(),
+        let content_type = get_content_type(&path);
+
+        // ziskame priponu souboru pro urceni typu obsahu
+        let ext = full_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        let cache_seconds = config.get_cache_duration(&ext);
+        let cache_control = if cache_seconds > 0 {
+            format!("public, max-age={}", cache_seconds)
+        } else {
+            "no-store, max-age=0".to_string()
         };
 
-        // ziskame timestamp pro posledni upravu souboru
-        let last_modified = match get_last_modified(&full_path) {
-            Some(time) => time,
-            None => String::new(),
+        // rozhodneme, jestli je obsah comprimovatelny
+        let should_compress =
+            config.enable_compression &&
+            is_compressible(&content_type) &&
+            size > (config.min_size_to_compress as u64);
+
+        // kodovani prijatelna pro klienta podle vah v Accept-Encoding
+        let accepted = ranked(
+            req.headers()
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok()),
+            &config.compression_preference
+        );
+
+        // vybereme kompresi podle toho, co je podporovano
+        let encoding = match accepted.first() {
+            Some(encoding) if should_compress && *encoding != Encoding::Identity => Some(*encoding),
+            _ => None,
+        };
+
+        // predkomprimovany soubor vedle zdroje (foo.css.br, foo.css.gz) ma prednost
+        let precompressed = if config.enable_compression {
+            find_precompressed(req, config, root, full_path, &accepted)
+        } else {
+            None
+        };
+
+        // v cache jsou jen cele odpovedi - Range a predkomprimovane soubory jdou z disku
+        let cache = ResponseCache::of(req).filter(|cache| cache.accepts(size));
+        let cache_key = match &cache {
+            Some(_) if precompressed.is_none() && !req.headers().contains_key(header::RANGE) => {
+                let variant = format!(
+                    "{}|{}|{}",
+                    encoding.unwrap_or(Encoding::Identity).as_str(),
+                    cache_control,
+                    config.etag.as_str()
+                );
+                CacheKey::for_file(full_path, &metadata, variant)
+            }
+            _ => None,
         };
 
+        if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+            if let Some(cached) = cache.get(key) {
+                return cached_response(req, &cached);
+            }
+        }
+
+        // vygenerujeme ETag pro soubor (podle rezimu z metadat nebo z obsahu)
+        let etag = generate_etag(full_path, &metadata, config.etag).unwrap_or_default();
+
+        // ziskame timestamp pro posledni upravu souboru
+        let last_modified = get_last_modified(full_path).unwrap_or_default();
+
         // handle podminenych pozadavku (If-None-Match a If-Modified-Since)
+        if let Some(response) = not_modified(req, &etag, &last_modified) {
+            return response;
+        }
+
+        // Range request (206/416) - cast souboru se posila bez komprese
+        let range = range_response(
+            req,
+            full_path,
+            &content_type,
+            size,
+            &etag,
+            &last_modified,
+            &cache_control
+        );
+        if let Some(response) = range {
+            return response;
+        }
+
+        if let Some((sidecar, enc)) = precompressed {
+            return serve_precompressed(
+                &sidecar,
+                enc,
+                &content_type,
+                &cache_control,
+                &etag,
+                &last_modified
+            );
+        }
+
+        // sestavime response na zaklade content type
+        let mut builder = HttpResponse::Ok();
+        let mut response = builder
+            .content_type(content_type)
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .insert_header((header::ACCEPT_RANGES, "bytes"));
+
+        // komprimovane telo je jina reprezentace - silny ETag by nebyl pravdivy
+        let etag = if encoding.is_some() { weak(&etag) } else { etag };
+
         if !etag.is_empty() {
-            if let Some(if_none_match) = req.headers().get(header::IF_NONE_MATCH) {
-                if let Ok(if_none_match_str) = if_none_match.to_str() {
-                    if if_none_match_str == etag {
-                        return HttpResponse::NotModified()
-                            .insert_header((header::ETAG, etag))
-                            .finish();
-                    }
-                }
-            }
+            response = response.insert_header((header::ETAG, etag.clone()));
         }
 
         if !last_modified.is_empty() {
-            if let Some(if_modified_since) = req.headers().get(header::IF_MODIFIED_SINCE) {
-                if let Ok(if_modified_since_str) = if_modified_since.to_str() {
-                    if if_modified_since_str == last_modified {
-                        return HttpResponse::NotModified()
-                            .insert_header((header::LAST_MODIFIED, last_modified))
-                            .finish();
-                    }
-                }
-            }
+            response = response.insert_header((header::LAST_MODIFIED, last_modified.clone()));
+        }
+
+        // odpoved se lisi podle Accept-Encoding - komprese za behu nebo predkomprimovany soubor
+        if should_compress || (config.enable_compression && has_precompressed(full_path)) {
+            response = response.insert_header((header::VARY, "Accept-Encoding"));
+        }
+
+        if let Some(enc) = encoding {
+            response = response.insert_header((header::CONTENT_ENCODING, enc.as_str()));
         }
 
-        let content = match fs::read(&full_path) {
-            Ok(c) => c,
-            Err(_) => {
-                return HttpResponse::InternalServerError().body("chyba pri cteni souboru");
+        // komprimovane telo nema predem znamou delku, posila se chunked
+        let response = match encoding {
+            Some(enc) => {
+                let levels = config.compression_levels;
+                response.streaming(compressed_stream(full_path.to_path_buf(), enc, levels))
             }
+            None =>
+                match file_body(full_path, size) {
+                    Ok(body) => response.body(body),
+                    Err(_) => {
+                        return HttpResponse::InternalServerError().body("chyba pri cteni souboru");
+                    }
+                }
         };
 
-        let content_type = get_content_type(&path);
+        // mensi soubory se mimo worker nactou (a zkomprimuji) cele a ulozi do cache;
+        // tato odpoved se mezitim streamuje z disku
+        if let (Some(cache), Some(key)) = (cache, cache_key) {
+            let cached = CachedResponse {
+                body: Bytes::new(),
+                etag,
+                last_modified,
+                headers: response
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+            };
+            let path = full_path.to_path_buf();
+            let levels = config.compression_levels;
 
-        // rozhodneme, jestli je obsah comprimovatelny
-        let should_compress =
-            config.enable_compression &&
-            is_compressible(&content_type) &&
-            content.len() > config.min_size_to_compress;
-
-        // ziskame hodnotu Accept-Encoding z requestu pro porovnani s podporovanymi typy komprese
-        let accepted_encodings = match req.headers().get(header::ACCEPT_ENCODING) {
-            Some(val) =>
-                match val.to_str() {
-                    Ok(encodings) => encodings.to_lowercase(),
-                    Err(_) => String::new(),
-                }
-            None => String::new(),
+            tokio::task::spawn_blocking(move || {
+                fill_cache(&cache, key, cached, &path, encoding, &levels, &metadata)
+            });
+        }
+
+        response
+    }
+}
+
+// telo pro cache; soubor zmeneny behem cteni se neulozi - klic uz by neodpovidal
+fn fill_cache(
+    cache: &ResponseCache,
+    key: CacheKey,
+    mut cached: CachedResponse,
+    path: &Path,
+    encoding: Option<Encoding>,
+    levels: &CompressionLevels,
+    metadata: &fs::Metadata
+) {
+    let body = match encoding {
+        Some(enc) => compress_file(path, enc, levels, Vec::new()),
+        None => fs::read(path),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            println!("chyba pri plneni cache {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    let unchanged = fs::metadata(path)
+        .map(|current| {
+            current.len() == metadata.len() && current.modified().ok() == metadata.modified().ok()
+        })
+        .unwrap_or(false);
+
+    if unchanged {
+        cached.body = Bytes::from(body);
+        cache.insert(key, Arc::new(cached));
+    }
+}
+
+/// 304 pro If-None-Match / If-Modified-Since shodne s aktualnim ETagem nebo Last-Modified
+pub fn not_modified(req: &HttpRequest, etag: &str, last_modified: &str) -> Option<HttpResponse> {
+    // s If-None-Match se If-Modified-Since podle RFC 9110 ignoruje
+    if let Some(if_none_match) = req.headers().get(header::IF_NONE_MATCH) {
+        return match if_none_match.to_str() {
+            Ok(if_none_match) if none_match(if_none_match, etag) => {
+                Some(not_modified_response(etag, last_modified))
+            }
+            _ => None,
         };
+    }
 
-        // aplikujeme kompresi podle toho, co je podporovano
-        let (body, encoding) = if should_compress {
-            if accepted_encodings.contains("br") {
-                match compress_brotli(&content) {
-                    Ok(compressed) => (compressed, Some("br")),
-                    Err(_) => (content, None),
+    if !last_modified.is_empty() {
+        if let Some(if_modified_since) = req.headers().get(header::IF_MODIFIED_SINCE) {
+            if let Ok(if_modified_since_str) = if_modified_since.to_str() {
+                if if_modified_since_str == last_modified {
+                    return Some(not_modified_response(etag, last_modified));
                 }
-            } else if accepted_encodings.contains("gzip") {
-                match compress_gzip(&content) {
-                    Ok(compressed) => (compressed, Some("gzip")),
-                    Err(_) => (content, None),
-                }
-            } else if accepted_encodings.contains("deflate") {
-                match compress_deflate(&content) {
-                    Ok(compressed) => (compressed, Some("deflate")),
-                    Err(_) => (content, None),
-                }
-            } else {
-                (content, None)
             }
-        } else {
-            (content, None)
-        };
+        }
+    }
 
-        // ziskame priponu souboru pro urceni typu obsahu
-        let ext = Path::new(&path)
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_string();
+    None
+}
 
-        let cache_seconds = config.get_cache_duration(&ext);
+fn not_modified_response(etag: &str, last_modified: &str) -> HttpResponse {
+    let mut response = HttpResponse::NotModified();
+    if !etag.is_empty() {
+        response.insert_header((header::ETAG, etag));
+    }
+    if !last_modified.is_empty() {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+    response.finish()
+}
 
-        // sestavime response na zaklade content type
-        let mut builder = HttpResponse::Ok();
-        let mut response = builder.content_type(content_type);
+/// odpoved z cache - bez cteni, hashovani a komprese souboru
+pub fn cached_response(req: &HttpRequest, cached: &CachedResponse) -> HttpResponse {
+    if let Some(response) = not_modified(req, &cached.etag, &cached.last_modified) {
+        return response;
+    }
 
-        if cache_seconds > 0 {
-            response = response.insert_header((
-                header::CACHE_CONTROL,
-                format!("public, max-age={}", cache_seconds),
-            ));
-        } else {
-            response = response.insert_header((header::CACHE_CONTROL, "no-store, max-age=0"));
-        }
+    let mut response = HttpResponse::Ok();
+    for (name, value) in &cached.headers {
+        response.append_header((name.clone(), value.clone()));
+    }
 
-        if !etag.is_empty() {
-            response = response.insert_header((header::ETAG, etag));
-        }
+    response.body(cached.body.clone())
+}
 
-        if !last_modified.is_empty() {
-            response = response.insert_header((header::LAST_MODIFIED, last_modified));
+/// odpoved na Range request - 206 s jednim rozsahem nebo multipart/byteranges,
+/// 416 pro rozsahy mimo soubor; None znamena poslat cely soubor
+fn range_response(
+    req: &HttpRequest,
+    full_path: &Path,
+    content_type: &str,
+    size: u64,
+    etag: &str,
+    last_modified: &str,
+    cache_control: &str
+) -> Option<HttpResponse> {
+    // Range se podle RFC 9110 vyhodnocuje jen pro GET
+    if req.method() != Method::GET {
+        return None;
+    }
+
+    let range = req.headers().get(header::RANGE)?.to_str().ok()?;
+
+    // soubor se od stazeni prvni casti zmenil - posleme ho cely
+    if let Some(if_range) = req.headers().get(header::IF_RANGE) {
+        match if_range.to_str() {
+            Ok(if_range) if if_range_matches(if_range, etag, last_modified) => {}
+            _ => {
+                return None;
+            }
         }
+    }
 
-        // pridame content length header
-        if let Some(enc) = encoding {
-            response = response.insert_header((header::CONTENT_ENCODING, enc));
+    let ranges = match parse_range(range, size) {
+        RangeRequest::Full => {
+            return None;
+        }
+        RangeRequest::Unsatisfiable => {
+            return Some(
+                HttpResponse::RangeNotSatisfiable()
+                    .insert_header((header::CONTENT_RANGE, format!("bytes */{}", size)))
+                    .insert_header((header::ACCEPT_RANGES, "bytes"))
+                    .finish()
+            );
         }
+        RangeRequest::Partial(ranges) => ranges,
+    };
+
+    let mut response = HttpResponse::PartialContent();
+    response
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CACHE_CONTROL, cache_control));
 
-        response.body(body)
+    if !etag.is_empty() {
+        response.insert_header((header::ETAG, etag));
     }
+    if !last_modified.is_empty() {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+
+    let body = if let [range] = ranges.as_slice() {
+        response
+            .content_type(content_type)
+            .insert_header((header::CONTENT_RANGE, range.content_range(size)));
+        open_range(full_path, *range).map(|file| {
+            SizedStream::new(range.length(), ReaderStream::with_capacity(file, CHUNK_SIZE)).boxed()
+        })
+    } else {
+        let boundary = format!("{:016x}", rand::thread_rng().gen::<u64>());
+        response.content_type(format!("multipart/byteranges; boundary={}", boundary));
+        multipart_stream(full_path, &ranges, size, content_type, &boundary).map(|(length, stream)| {
+            SizedStream::new(length, stream).boxed()
+        })
+    };
+
+    Some(match body {
+        Ok(body) => response.body(body),
+        Err(_) => HttpResponse::InternalServerError().body("chyba pri cteni souboru"),
+    })
 }
 
-// kontrola jestli cesta neobsahuje ".." nebo "..\"
-fn normalize_path(path: &str) -> Result<String, io::Error> {
-    let path = path.replace('\\', "/");
-    let normalized = Path::new(&path)
-        .components()
-        .fold(PathBuf::new(), |mut result, p| {
-            match p {
-                std::path::Component::Normal(x) => result.push(x),
-                // preskocime vsechny ostatni typy komponent (jako ParentDir, ktery by mohl byt pouzit pro directory traversal)
-                _ => {}
+/// najde predkomprimovanou variantu v poradi podle Accept-Encoding; varianta
+/// starsi nez zdrojovy soubor nebo zakazana jako kazdy jiny soubor v koreni
+/// (`deny_patterns`, symlink ven) se ignoruje
+fn find_precompressed(
+    req: &HttpRequest,
+    config: &ServerConfig,
+    root: &Path,
+    full_path: &Path,
+    accepted: &[Encoding]
+) -> Option<(PathBuf, Encoding)> {
+    let source_modified = fs::metadata(full_path).and_then(|m| m.modified()).ok()?;
+    let relative = full_path.strip_prefix(root).ok()?.to_str()?.replace('\\', "/");
+
+    // identity s vyssi vahou nez zbyle kodeky ukonci hledani
+    for encoding in accepted.iter().take_while(|e| **e != Encoding::Identity) {
+        let extension = match encoding.sidecar_extension() {
+            Some(extension) => extension,
+            None => {
+                continue;
             }
-            result
-        });
+        };
+
+        let sidecar = sidecar_path(full_path, extension);
+        let modified = match fs::metadata(&sidecar) {
+            Ok(metadata) if metadata.is_file() => metadata.modified().ok(),
+            _ => {
+                continue;
+            }
+        };
+
+        let sidecar_relative = format!("{}.{}", relative, extension);
+        if let Err(denied) = check_access(root, &sidecar_relative, config) {
+            log_violation(req, &sidecar, &denied);
+            continue;
+        }
 
-    match normalized.to_str() {
-        Some(s) => Ok(s.to_string()),
-        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "chybna cesta")),
+        match modified {
+            Some(modified) if modified >= source_modified => {
+                return Some((sidecar, *encoding));
+            }
+            _ => {
+                // jednou pro kazdou verzi zdroje, ne pri kazdem requestu
+                let key = (sidecar.clone(), source_modified);
+                if STALE_SIDECARS.lock().unwrap().insert(key) {
+                    let sidecar = sidecar.display();
+                    println!("predkomprimovany {} je starsi nez zdroj, ignoruji ho", sidecar);
+                }
+            }
+        }
     }
+
+    None
 }
 
-fn get_content_type(path: &str) -> String {
-    let mime = from_path(path).first_or_octet_stream();
-    mime.to_string()
+fn has_precompressed(full_path: &Path) -> bool {
+    [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+        .iter()
+        .filter_map(|encoding| encoding.sidecar_extension())
+        .any(|extension| sidecar_path(full_path, extension).is_file())
 }
 
-fn generate_etag(path: &Path) -> Result<String, io::Error> {
-    let mut file = fs::File::open(path)?;
-    let mut context = Context::new(&SHA256);
-    let mut buffer = [0; 4096];
+fn sidecar_path(full_path: &Path, extension: &str) -> PathBuf {
+    let mut path = full_path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
 
-    loop {
-        let count = file.read(&mut buffer)?;
-        if count == 0 {
-            break;
+// predkomprimovany soubor s typem obsahu puvodniho souboru
+fn serve_precompressed(
+    sidecar: &Path,
+    encoding: Encoding,
+    content_type: &str,
+    cache_control: &str,
+    etag: &str,
+    last_modified: &str
+) -> HttpResponse {
+    let size = match fs::metadata(sidecar) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("chyba pri ziskavani metadat souboru");
         }
-        context.update(&buffer[..count]);
+    };
+
+    let body = match file_body(sidecar, size) {
+        Ok(body) => body,
+        Err(_) => {
+            return HttpResponse::InternalServerError().body("chyba pri cteni souboru");
+        }
+    };
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .insert_header((header::CONTENT_ENCODING, encoding.as_str()))
+        .insert_header((header::VARY, "Accept-Encoding"))
+        .insert_header((header::CACHE_CONTROL, cache_control));
+
+    // jina reprezentace nez puvodni soubor - ETag uz nemuze byt silny
+    if !etag.is_empty() {
+        response.insert_header((header::ETAG, weak(etag)));
+    }
+    if !last_modified.is_empty() {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
     }
 
-    let digest = context.finish();
-    let etag = format!("\"{}\"", hex::encode(digest.as_ref()));
-    Ok(etag)
+    response.body(body)
+}
+
+// cely soubor jako stream se znamou delkou (Content-Length zustava)
+fn file_body(
+    path: &Path,
+    size: u64
+) -> Result<SizedStream<ReaderStream<tokio::fs::File>>, io::Error> {
+    let file = tokio::fs::File::from_std(fs::File::open(path)?);
+    Ok(SizedStream::new(size, ReaderStream::with_capacity(file, CHUNK_SIZE)))
+}
+
+fn get_content_type(path: &str) -> String {
+    let mime = from_path(path).first_or_octet_stream();
+    mime.to_string()
 }
 
 fn get_last_modified(path: &Path) -> Option<String> {
@@ -265,10 +739,7 @@ fn get_last_modified(path: &Path) -> Option<String> {
             if let Ok(duration) = modified.duration_since(UNIX_EPOCH) {
                 let timestamp = duration.as_secs();
                 // prevedeme timestamp na UTC a formatneme ho do spravneho formatu
-                let dt = chrono::DateTime::<chrono::Utc>::from_utc(
-                    chrono::NaiveDateTime::from_timestamp_opt(timestamp as i64, 0)?,
-                    chrono::Utc
-                );
+                let dt = chrono::DateTime::from_timestamp(timestamp as i64, 0)?;
                 return Some(dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
             }
         }
@@ -276,37 +747,319 @@ fn get_last_modified(path: &Path) -> Option<String> {
     None
 }
 
-fn is_compressible(content_type: &str) -> bool {
-    let compressible_types = [
-        "text/",
-        "application/javascript",
-        "application/json",
-        "application/xml",
-        "application/xhtml+xml",
-        "image/svg+xml",
-        "application/wasm",
-    ];
-
-    compressible_types.iter().any(|&t| content_type.starts_with(t))
+// komprese bezi v blocking vlakne a komprimovana data posila po castech do odpovedi
+fn compressed_stream(
+    path: PathBuf,
+    encoding: Encoding,
+    levels: CompressionLevels
+) -> impl Stream<Item = Result<Bytes, io::Error>> {
+    let (tx, mut rx) = mpsc::channel(16);
+
+    tokio::task::spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter { tx: tx.clone() });
+        let result = compress_file(&path, encoding, &levels, writer).and_then(|writer| {
+            writer.into_inner().map_err(|e| e.into_error())
+        });
+
+        if let Err(e) = result {
+            // pri odpojenem klientovi uz zpravu nikdo neprecte
+            let _ = tx.blocking_send(Err(e));
+        }
+    });
+
+    stream::poll_fn(move |cx| rx.poll_recv(cx))
 }
 
-fn compress_brotli(content: &[u8]) -> Result<Vec<u8>, io::Error> {
-    let mut reader = CompressorReader::new(content, 4096, 4, 22);
-    let mut compressed = Vec::new();
-    reader.read_to_end(&mut compressed)?;
-    Ok(compressed)
+fn compress_file<W: Write>(
+    path: &Path,
+    encoding: Encoding,
+    levels: &CompressionLevels,
+    writer: W
+) -> Result<W, io::Error> {
+    let mut file = fs::File::open(path)?;
+    compress(&mut file, writer, encoding, levels)
 }
 
-fn compress_gzip(content: &[u8]) -> Result<Vec<u8>, io::Error> {
-    let mut encoder = GzEncoder::new(content, Compression::default());
-    let mut compressed = Vec::new();
-    encoder.read_to_end(&mut compressed)?;
-    Ok(compressed)
+// Write, ktery kazdy zapis posle jako chunk odpovedi
+struct ChannelWriter {
+    tx: mpsc::Sender<Result<Bytes, io::Error>>,
 }
 
-fn compress_deflate(content: &[u8]) -> Result<Vec<u8>, io::Error> {
-    let mut encoder = DeflateEncoder::new(content, Compression::default());
-    let mut compressed = Vec::new();
-    encoder.read_to_end(&mut compressed)?;
-    Ok(compressed)
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "klient ukoncil spojeni"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SymlinkPolicy;
+    use actix_web::test::TestRequest;
+
+    fn encoding_of(root: &Path, name: &str, config: &ServerConfig) -> Option<String> {
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_http_request();
+        let response = serve_file(&req, config, root, &root.join(name));
+
+        assert_eq!(response.status(), 200);
+        response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    fn config() -> ServerConfig {
+        ServerConfig {
+            enable_compression: true,
+            // test se ma tykat jen predkomprimovanych souboru
+            min_size_to_compress: usize::MAX,
+            follow_symlinks: SymlinkPolicy::Off,
+            ..ServerConfig::default()
+        }
+    }
+
+    #[test]
+    fn precompressed_sidecar_is_served() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.css"), "body {}").unwrap();
+        fs::write(dir.path().join("app.css.gz"), "gz").unwrap();
+
+        assert_eq!(encoding_of(dir.path(), "app.css", &config()).as_deref(), Some("gzip"));
+    }
+
+    #[test]
+    fn denied_sidecar_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.css"), "body {}").unwrap();
+        fs::write(dir.path().join("app.css.gz"), "gz").unwrap();
+
+        let config = ServerConfig {
+            deny_patterns: vec!["*.gz".to_string()],
+            ..config()
+        };
+        assert_eq!(encoding_of(dir.path(), "app.css", &config), None);
+    }
+
+    #[actix_web::test]
+    async fn cache_is_filled_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("app.css"), "body { color: red; }\n".repeat(100)).unwrap();
+
+        let cache = ResponseCache::new(1024 * 1024);
+        let config = ServerConfig { enable_compression: true, ..ServerConfig::default() };
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .app_data(web::Data::new(cache.clone()))
+            .to_http_request();
+        let serve = || serve_file(&req, &config, dir.path(), &dir.path().join("app.css"));
+
+        // prvni odpoved se streamuje, cache se plni mimo worker
+        let first = serve();
+        assert_eq!(first.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        let first_body = first.into_body().try_into_bytes().ok();
+        assert!(first_body.is_none());
+
+        for _ in 0..100 {
+            if cache.stats().entries == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(cache.stats().entries, 1);
+
+        let second = serve();
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(second.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+        let body = second.into_body().try_into_bytes().unwrap();
+        assert!(!body.is_empty());
+    }
+
+    #[test]
+    fn default_etag_resumes_with_if_range() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("video.mp4"), vec![7u8; 4096]).unwrap();
+        let config = ServerConfig::default();
+        let full_path = dir.path().join("video.mp4");
+
+        let req = TestRequest::default().to_http_request();
+        let first = serve_file(&req, &config, dir.path(), &full_path);
+        let etag = first.headers().get(header::ETAG).unwrap().to_str().unwrap().to_string();
+
+        let resume = |if_range: &str| {
+            let req = TestRequest::default()
+                .insert_header((header::RANGE, "bytes=1000-"))
+                .insert_header((header::IF_RANGE, if_range))
+                .to_http_request();
+            serve_file(&req, &config, dir.path(), &full_path).status()
+        };
+
+        assert_eq!(resume(&etag), 206);
+        assert_eq!(resume("\"jina-verze\""), 200);
+    }
+
+    // static_root s adresarem docs/ (index.htm, index.html, index.php)
+    fn static_site(index_files: &[&str]) -> (tempfile::TempDir, web::Data<Arc<ConfigManager>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("static");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        for name in ["index.htm", "index.html", "index.php"] {
+            fs::write(root.join("docs").join(name), name).unwrap();
+        }
+
+        let path = dir.path().join("server.toml");
+        let path = path.to_str().unwrap();
+        ServerConfig {
+            static_root: root.to_str().unwrap().to_string(),
+            index_files: index_files.iter().map(|name| name.to_string()).collect(),
+            ..ServerConfig::default()
+        }.save(path).unwrap();
+        let config_manager = Arc::new(ConfigManager::new(path).unwrap());
+
+        (dir, web::Data::new(config_manager))
+    }
+
+    async fn get_static(config_manager: &web::Data<Arc<ConfigManager>>, uri: &str) -> HttpResponse {
+        let req = TestRequest::get().uri(uri).to_http_request();
+        serve_static_file(req, config_manager.clone()).await
+    }
+
+    async fn body_of(response: HttpResponse) -> String {
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn directory_redirects_to_trailing_slash() {
+        let (_dir, config_manager) = static_site(&["index.html"]);
+
+        let response = get_static(&config_manager, "/static/docs?lang=cs&x=1").await;
+        assert_eq!(response.status(), 301);
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/static/docs/?lang=cs&x=1");
+
+        let response = get_static(&config_manager, "/static/docs").await;
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/static/docs/");
+
+        // bez indexu a autoindexu se nepresmerovava
+        let (_dir, config_manager) = static_site(&["default.html"]);
+        assert_eq!(get_static(&config_manager, "/static/docs").await.status(), 404);
+    }
+
+    #[actix_web::test]
+    async fn index_files_are_tried_in_order() {
+        let (_dir, config_manager) = static_site(&["index.html", "index.htm"]);
+        assert_eq!(body_of(get_static(&config_manager, "/static/docs/").await).await, "index.html");
+
+        let (_dir, config_manager) = static_site(&["missing.html", "index.htm", "index.html"]);
+        assert_eq!(body_of(get_static(&config_manager, "/static/docs/").await).await, "index.htm");
+
+        // PHP index se ze statickeho korene neposle jako zdrojak
+        let (_dir, config_manager) = static_site(&["index.php", "index.html"]);
+        assert_eq!(body_of(get_static(&config_manager, "/static/docs/").await).await, "index.html");
+    }
+
+    #[test]
+    fn accept_weights_decide_html_navigation() {
+        assert!(accepts_html(Some("text/html,application/xhtml+xml,*/*;q=0.8")));
+        assert!(accepts_html(Some("*/*")));
+        assert!(accepts_html(Some("text/*;q=0.5")));
+        assert!(!accepts_html(Some("text/html;q=0")));
+        // nejpresnejsi rozsah ma prednost pred `*/*`
+        assert!(!accepts_html(Some("text/html;q=0, */*")));
+        assert!(accepts_html(Some("*/*;q=0, text/html")));
+        assert!(!accepts_html(Some("application/json")));
+        assert!(!accepts_html(Some("text/html;q=1.5")));
+        assert!(!accepts_html(None));
+    }
+
+    // static_root s dokumentem aplikace a jednim skriptem
+    fn spa_site() -> (tempfile::TempDir, web::Data<Arc<ConfigManager>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("static");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("index.html"), "aplikace").unwrap();
+        fs::write(root.join("app.js"), "js").unwrap();
+
+        let path = dir.path().join("server.toml");
+        let path = path.to_str().unwrap();
+        ServerConfig {
+            static_root: root.to_str().unwrap().to_string(),
+            spa_fallback: Some("index.html".to_string()),
+            ..ServerConfig::default()
+        }.save(path).unwrap();
+        let config_manager = Arc::new(ConfigManager::new(path).unwrap());
+
+        (dir, web::Data::new(config_manager))
+    }
+
+    async fn get_accepting(
+        config_manager: &web::Data<Arc<ConfigManager>>,
+        uri: &str,
+        accept: &str
+    ) -> HttpResponse {
+        let req = TestRequest::get()
+            .uri(uri)
+            .insert_header((header::ACCEPT, accept))
+            .to_http_request();
+        serve_static_file(req, config_manager.clone()).await
+    }
+
+    #[actix_web::test]
+    async fn spa_fallback_serves_document_to_navigations() {
+        let (_dir, config_manager) = spa_site();
+
+        for (uri, accept) in [
+            ("/static/users/42", "text/html,*/*;q=0.8"),
+            ("/static/users/42", "*/*"),
+            ("/static/about.html", "text/html"),
+        ] {
+            let response = get_accepting(&config_manager, uri, accept).await;
+            assert_eq!(response.status(), 200, "{} {}", uri, accept);
+            assert!(response.headers().get_all(header::VARY).any(|v| v == "Accept"));
+            assert_eq!(body_of(response).await, "aplikace");
+        }
+
+        for (uri, accept) in [
+            ("/static/users/42", "text/html;q=0, */*"),
+            ("/static/users/42", "application/json"),
+        ] {
+            let response = get_accepting(&config_manager, uri, accept).await;
+            assert_eq!(response.status(), 404, "{} {}", uri, accept);
+        }
+
+        let response = get_accepting(&config_manager, "/static/app.js", "*/*").await;
+        assert_eq!(body_of(response).await, "js");
+    }
+
+    #[actix_web::test]
+    async fn spa_fallback_keeps_asset_404s() {
+        let (_dir, config_manager) = spa_site();
+
+        for name in ["app.js.map", "app.wasm", "data.json", "a.webp"] {
+            let uri = format!("/static/{}", name);
+            let response = get_accepting(&config_manager, &uri, "text/html,*/*").await;
+            assert_eq!(response.status(), 404, "{}", uri);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sidecar_symlink_outside_root_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("web");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("app.css"), "body {}").unwrap();
+        fs::write(dir.path().join("tajne"), "tajne").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("tajne"), root.join("app.css.gz")).unwrap();
+
+        assert_eq!(encoding_of(&root, "app.css", &config()), None);
+    }
 }
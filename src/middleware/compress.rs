@@ -0,0 +1,482 @@
+use actix_web::body::{ BodySize, BoxBody, EitherBody, MessageBody };
+use actix_web::dev::{ Service, ServiceRequest, ServiceResponse, Transform };
+use actix_web::http::header::{ self, HeaderMap, HeaderValue };
+use actix_web::http::{ Method, StatusCode };
+use actix_web::web::{ self, Bytes };
+use actix_web::Error;
+use futures::future::{ ok, Ready };
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ ready, Context, Poll };
+use crate::config::ConfigManager;
+use crate::utils::compression::{ is_compressible, negotiate, StreamEncoder };
+
+/// komprese odpovedi, ktere handler nezkomprimoval sam (PHP, JS, proxy, API);
+/// staticke soubory uz prichazi s Content-Encoding a projdou beze zmeny
+pub struct Compress;
+
+impl<S, B> Transform<S, ServiceRequest>
+    for Compress
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: MessageBody + 'static
+{
+    type Response = ServiceResponse<EitherBody<B, CompressedBody>>;
+    type Error = Error;
+    type Transform = CompressMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CompressMiddleware { service })
+    }
+}
+
+pub struct CompressMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest>
+    for CompressMiddleware<S>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: MessageBody + 'static
+{
+    type Response = ServiceResponse<EitherBody<B, CompressedBody>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let accept_encoding = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let is_head = req.method() == Method::HEAD;
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let res = fut.await?;
+
+            if is_head || !is_eligible(res.status(), res.headers(), res.response().body().size()) {
+                return Ok(res.map_into_left_body());
+            }
+
+            // konfigurace az po handleru - v extensions uz je nalezena location
+            let config = match
+                res.request().app_data::<web::Data<Arc<ConfigManager>>>()
+            {
+                Some(config_manager) => config_manager.get_request_config(res.request()),
+                None => {
+                    return Ok(res.map_into_left_body());
+                }
+            };
+
+            if !config.enable_compression {
+                return Ok(res.map_into_left_body());
+            }
+            if let BodySize::Sized(size) = res.response().body().size() {
+                if size <= (config.min_size_to_compress as u64) {
+                    return Ok(res.map_into_left_body());
+                }
+            }
+
+            let encoding = negotiate(accept_encoding.as_deref(), &config.compression_preference);
+            let encoder = match StreamEncoder::new(encoding, &config.compression_levels) {
+                Ok(encoder) => encoder,
+                Err(e) => {
+                    println!("chyba pri vytvareni kompresoru {}: {}", encoding.as_str(), e);
+                    None
+                }
+            };
+
+            let mut res = res;
+            append_vary(res.headers_mut());
+
+            let encoder = match encoder {
+                Some(encoder) => encoder,
+                None => {
+                    return Ok(res.map_into_left_body());
+                }
+            };
+
+            let headers = res.headers_mut();
+            headers.remove(header::CONTENT_LENGTH);
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+            weaken_etag(headers);
+
+            // puvodni delka (proxy, CGI) uz neplati - bez chunked by klient nepoznal konec tela
+            res.response_mut().head_mut().no_chunking(false);
+
+            Ok(
+                res.map_body(|_, body| {
+                    EitherBody::right(CompressedBody {
+                        body: body.boxed(),
+                        encoder: Some(encoder),
+                    })
+                })
+            )
+        })
+    }
+}
+
+// odpovedi, u kterych komprese nedava smysl nebo by rozbila semantiku
+fn is_eligible(status: StatusCode, headers: &HeaderMap, size: BodySize) -> bool {
+    if !status.is_success() || status == StatusCode::NO_CONTENT {
+        return false;
+    }
+    // 206 by po kompresi neodpovidalo Content-Range
+    if status == StatusCode::PARTIAL_CONTENT {
+        return false;
+    }
+    if matches!(size, BodySize::None | BodySize::Sized(0)) {
+        return false;
+    }
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+
+    let no_transform = headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_ascii_lowercase().contains("no-transform"))
+        .unwrap_or(false);
+    if no_transform {
+        return false;
+    }
+
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(is_compressible)
+        .unwrap_or(false)
+}
+
+// odpoved se lisi podle Accept-Encoding, i kdyz ji zrovna posilame nekomprimovanou
+fn append_vary(headers: &mut HeaderMap) {
+    let present = headers
+        .get_all(header::VARY)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| {
+            let value = value.trim();
+            value == "*" || value.eq_ignore_ascii_case("accept-encoding")
+        });
+
+    if !present {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+// zkomprimovane telo uz neni bajtove shodne s puvodnim
+fn weaken_etag(headers: &mut HeaderMap) {
+    let etag = match headers.get(header::ETAG).and_then(|value| value.to_str().ok()) {
+        Some(etag) if !etag.starts_with("W/") => format!("W/{}", etag),
+        _ => {
+            return;
+        }
+    };
+
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+}
+
+/// telo komprimovane za behu; kazda cast se hned flushne, takze streamovany
+/// vystup PHP nebo proxy dorazi ke klientovi prubezne
+pub struct CompressedBody {
+    body: BoxBody,
+    encoder: Option<StreamEncoder>,
+}
+
+impl MessageBody for CompressedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+
+        loop {
+            let encoder = match this.encoder.as_mut() {
+                Some(encoder) => encoder,
+                None => {
+                    return Poll::Ready(None);
+                }
+            };
+
+            match ready!(Pin::new(&mut this.body).poll_next(cx)) {
+                Some(Ok(chunk)) => {
+                    match encoder.write(&chunk) {
+                        // kompresor zatim nic nevydal, cteme dal
+                        Ok(output) if output.is_empty() => {
+                            continue;
+                        }
+                        Ok(output) => {
+                            return Poll::Ready(Some(Ok(Bytes::from(output))));
+                        }
+                        Err(e) => {
+                            this.encoder = None;
+                            return Poll::Ready(Some(Err(e.into())));
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    this.encoder = None;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                None => {
+                    let encoder = this.encoder.take().unwrap();
+                    return match encoder.finish() {
+                        Ok(output) if output.is_empty() => Poll::Ready(None),
+                        Ok(output) => Poll::Ready(Some(Ok(Bytes::from(output)))),
+                        Err(e) => Poll::Ready(Some(Err(e.into()))),
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use actix_web::{ test as actix_test, App, HttpResponse, HttpServer };
+    use flate2::read::GzDecoder;
+    use futures::stream;
+    use std::io::{ self, Read, Write };
+    use std::net::TcpStream;
+    use std::path::Path;
+
+    fn text() -> String {
+        "Prilis zlutoucky kun upel dabelske ody. ".repeat(100)
+    }
+
+    fn config_manager(dir: &Path) -> Arc<ConfigManager> {
+        let path = dir.join("server.toml");
+        let path = path.to_str().unwrap();
+        ServerConfig::default().save(path).unwrap();
+        Arc::new(ConfigManager::new(path).unwrap())
+    }
+
+    fn gunzip(body: &[u8]) -> String {
+        let mut text = String::new();
+        GzDecoder::new(body).read_to_string(&mut text).unwrap();
+        text
+    }
+
+    // telo se znamou delkou poslane jako stream - tak odpovida proxy i CGI
+    async fn streamed_with_length() -> HttpResponse {
+        let chunks: Vec<Result<Bytes, Error>> = text()
+            .into_bytes()
+            .chunks(512)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        HttpResponse::Ok()
+            .content_type("text/plain")
+            .no_chunking(text().len() as u64)
+            .streaming(stream::iter(chunks))
+    }
+
+    fn app(
+        config_manager: Arc<ConfigManager>
+    ) -> App<
+        impl actix_web::dev::ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = Error,
+            InitError = ()
+        >
+    > {
+        let text_response = |content_type: &'static str| {
+            move || async move {
+                HttpResponse::Ok()
+                    .content_type(content_type)
+                    .insert_header((header::ETAG, "\"abc\""))
+                    .body(text())
+            }
+        };
+
+        App::new()
+            .app_data(web::Data::new(config_manager))
+            .wrap(Compress)
+            .route("/text", web::to(text_response("text/plain")))
+            .route("/png", web::get().to(text_response("image/png")))
+            .route("/stream", web::get().to(streamed_with_length))
+            .route("/small", web::get().to(|| async { HttpResponse::Ok().body("ahoj") }))
+            .route(
+                "/encoded",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("text/plain")
+                        .insert_header((header::CONTENT_ENCODING, "br"))
+                        .body(text())
+                })
+            )
+            .route(
+                "/partial",
+                web::get().to(|| async {
+                    HttpResponse::PartialContent().content_type("text/plain").body(text())
+                })
+            )
+            .route(
+                "/no-transform",
+                web::get().to(|| async {
+                    HttpResponse::Ok()
+                        .content_type("text/plain")
+                        .insert_header((header::CACHE_CONTROL, "public, no-transform"))
+                        .body(text())
+                })
+            )
+    }
+
+    fn get(uri: &str, accept_encoding: Option<&str>) -> actix_test::TestRequest {
+        let request = actix_test::TestRequest::get().uri(uri);
+        match accept_encoding {
+            Some(value) => request.insert_header((header::ACCEPT_ENCODING, value)),
+            None => request,
+        }
+    }
+
+    fn header_of(response: &ServiceResponse<impl MessageBody>, name: header::HeaderName) -> String {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    #[actix_web::test]
+    async fn negotiated_encoding_rewrites_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(app(config_manager(dir.path()))).await;
+
+        let response = actix_test::call_service(
+            &app,
+            get("/text", Some("br;q=0, gzip")).to_request()
+        ).await;
+        assert_eq!(header_of(&response, header::CONTENT_ENCODING), "gzip");
+        assert_eq!(header_of(&response, header::VARY), "accept-encoding");
+        assert_eq!(header_of(&response, header::ETAG), "W/\"abc\"");
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(gunzip(&actix_test::read_body(response).await), text());
+
+        // bez komprese zustava telo i ETag, Vary se pridava vzdy
+        for accept_encoding in [Some("identity"), Some("br;q=0, gzip;q=0"), None] {
+            let response = actix_test::call_service(
+                &app,
+                get("/text", accept_encoding).to_request()
+            ).await;
+            assert_eq!(header_of(&response, header::CONTENT_ENCODING), "");
+            assert_eq!(header_of(&response, header::VARY), "accept-encoding");
+            assert_eq!(header_of(&response, header::ETAG), "\"abc\"");
+            assert_eq!(actix_test::read_body(response).await, text());
+        }
+    }
+
+    #[actix_web::test]
+    async fn skips_ineligible_responses() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(app(config_manager(dir.path()))).await;
+
+        for (uri, encoding) in [
+            ("/small", ""),
+            ("/png", ""),
+            ("/encoded", "br"),
+            ("/partial", ""),
+            ("/no-transform", ""),
+        ] {
+            let response = actix_test::call_service(
+                &app,
+                get(uri, Some("gzip")).to_request()
+            ).await;
+            assert_eq!(header_of(&response, header::CONTENT_ENCODING), encoding, "{}", uri);
+        }
+
+        let head = actix_test::TestRequest::default()
+            .method(Method::HEAD)
+            .uri("/text")
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let response = actix_test::call_service(&app, head).await;
+        assert_eq!(header_of(&response, header::CONTENT_ENCODING), "");
+    }
+
+    #[actix_web::test]
+    async fn compressed_stream_with_length_is_chunked() {
+        let dir = tempfile::tempdir().unwrap();
+        let app = actix_test::init_service(app(config_manager(dir.path()))).await;
+
+        let request = get("/stream", Some("gzip")).to_request();
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(header_of(&response, header::CONTENT_ENCODING), "gzip");
+        assert!(response.response().head().chunked());
+        assert_eq!(gunzip(&actix_test::read_body(response).await), text());
+
+        // bez komprese zustava puvodni Content-Length
+        let response = actix_test::call_service(&app, get("/stream", None).to_request()).await;
+        assert!(!response.response().head().chunked());
+    }
+
+    #[actix_web::test]
+    async fn compressed_stream_with_length_is_chunked_on_the_wire() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_manager = config_manager(dir.path());
+        let server = HttpServer::new(move || app(config_manager.clone()))
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let raw = web::block(move || {
+            let mut stream = TcpStream::connect(address)?;
+            stream.write_all(
+                b"GET /stream HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\
+                  Connection: close\r\n\r\n"
+            )?;
+            let mut raw = Vec::new();
+            stream.read_to_end(&mut raw)?;
+            Ok::<_, io::Error>(raw)
+        }).await
+            .unwrap()
+            .unwrap();
+
+        let end = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&raw[..end]).to_ascii_lowercase();
+        assert!(head.contains("transfer-encoding: chunked"), "{}", head);
+        assert!(!head.contains("content-length"), "{}", head);
+        assert_eq!(gunzip(&dechunk(&raw[end + 4..])), text());
+    }
+
+    // telo v chunked kodovani (RFC 9112, 7.1) bez trailer hlavicek
+    fn dechunk(mut raw: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        loop {
+            let line_end = raw.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size = std::str::from_utf8(&raw[..line_end]).unwrap();
+            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+            raw = &raw[line_end + 2..];
+            if size == 0 {
+                return body;
+            }
+            body.extend_from_slice(&raw[..size]);
+            raw = &raw[size + 2..];
+        }
+    }
+}
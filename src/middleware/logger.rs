@@ -5,6 +5,7 @@ use std::pin::Pin;
 use std::task::{ Context, Poll };
 use actix_web::dev::{ Service, Transform };
 use futures::future::{ ok, Ready };
+use crate::server::tls::TlsCertInfo;
 
 // funkce middlwaru pro ServiceRequest/ServiceResponse
 pub async fn log_requests<B>(
@@ -24,6 +25,7 @@ pub async fn log_requests<B>(
 }
 
 // middleware struktura pro logovani
+#[derive(Default)]
 pub struct Logger;
 
 impl Logger {
@@ -70,7 +72,17 @@ impl<S, B> Service<ServiceRequest>
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let start_time = Instant::now();
 
-        println!("prijmul jsem request: {} {}", req.method(), req.uri());
+        match req.conn_data::<TlsCertInfo>() {
+            Some(tls) =>
+                println!(
+                    "prijmul jsem request: {} {} (SNI: {}, certifikat: {})",
+                    req.method(),
+                    req.uri(),
+                    tls.server_name.as_deref().unwrap_or("-"),
+                    tls.certificate
+                ),
+            None => println!("prijmul jsem request: {} {}", req.method(), req.uri()),
+        }
 
         let fut = self.service.call(req);
 
@@ -1,5 +1,7 @@
 pub mod logger;
 pub mod error_handler;
+pub mod rewrite;
+pub mod compress;
 
 pub use logger::log_requests;
 pub use error_handler::error_handler;
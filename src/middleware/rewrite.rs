@@ -0,0 +1,663 @@
+use actix_web::{ dev::ServiceRequest, dev::ServiceResponse, web, Error, HttpMessage, HttpResponse };
+use actix_web::body::EitherBody;
+use actix_web::dev::{ Service, Transform };
+use actix_web::http::{ header, StatusCode, Uri };
+use actix_web::http::uri::PathAndQuery;
+use futures::future::{ ok, Ready };
+use regex::{ Captures, Regex };
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{ Arc, RwLock };
+use std::task::{ Context, Poll };
+use crate::config::{ ConfigManager, RewriteCondition, RewriteFlag, RewriteRule };
+use crate::utils::path_resolver::{ contain, resolve };
+use crate::utils::strip_port;
+
+// ochrana proti zacykleni pravidel s priznakem `last`
+const MAX_ROUNDS: usize = 10;
+
+/// request pro vyhodnoceni pravidel; oddeleny od actixu, aby slo pravidla
+/// zkouset bez beziciho serveru
+#[derive(Debug, Clone, Default)]
+pub struct RewriteInput {
+    pub path: String,
+    pub query: String,
+    pub host: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    /// koren pro `file_exists` bez vlastniho `root` (`server_directory` hostu)
+    pub document_root: String,
+}
+
+/// URI requestu pred internim prepisem (pro REQUEST_URI v PHP)
+#[derive(Debug, Clone)]
+pub struct OriginalUri(pub String);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RewriteOutcome {
+    Unchanged,
+    /// interni prepis - request pokracuje s novou cestou a query stringem
+    Rewrite {
+        path: String,
+        query: String,
+    },
+    Redirect {
+        status: u16,
+        location: String,
+    },
+}
+
+/// zkompilovana sada `[[rewrite]]` pravidel
+pub struct RewriteEngine {
+    rules: Vec<CompiledRule>,
+}
+
+struct CompiledRule {
+    pattern: Regex,
+    target: String,
+    flags: Vec<RewriteFlag>,
+    conditions: Vec<CompiledCondition>,
+}
+
+struct CompiledCondition {
+    kind: ConditionKind,
+    negate: bool,
+}
+
+enum ConditionKind {
+    Host(Regex),
+    Method(Vec<String>),
+    Header {
+        name: String,
+        value: Option<Regex>,
+    },
+    FileExists {
+        path: String,
+        root: Option<String>,
+    },
+}
+
+impl RewriteInput {
+    pub fn from_request(req: &ServiceRequest) -> Self {
+        let host = req.connection_info().host().to_string();
+        let document_root = req
+            .app_data::<web::Data<Arc<ConfigManager>>>()
+            .map(|config_manager| config_manager.get_config().for_host(&host).server_directory)
+            .unwrap_or_default();
+
+        RewriteInput {
+            document_root,
+            path: req.path().to_string(),
+            query: req.query_string().to_string(),
+            host: strip_port(req.connection_info().host()).to_string(),
+            method: req.method().to_string(),
+            headers: req
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (name.as_str().to_string(), value.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl RewriteEngine {
+    pub fn compile(rules: &[RewriteRule]) -> Result<Self, String> {
+        let rules = rules
+            .iter()
+            .map(compile_rule)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RewriteEngine { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// projde pravidla v poradi z konfigurace; presmerovani konci hned,
+    /// interni prepis pokracuje dalsim pravidlem s novou URL
+    pub fn evaluate(&self, input: &RewriteInput) -> RewriteOutcome {
+        let mut path = input.path.clone();
+        let mut query = input.query.clone();
+
+        'rounds: for round in 1..=MAX_ROUNDS {
+            for rule in &self.rules {
+                let captures = match rule.pattern.captures(&path) {
+                    Some(captures) => captures,
+                    None => {
+                        continue;
+                    }
+                };
+
+                if !rule.conditions.iter().all(|c| c.matches(input, &captures)) {
+                    continue;
+                }
+
+                let mut target = String::new();
+                captures.expand(&rule.target, &mut target);
+                let (new_path, new_query) = split_target(&target, &query, rule.has(RewriteFlag::Qsa));
+
+                if let Some(status) = rule.redirect_status() {
+                    let location = if new_query.is_empty() {
+                        new_path
+                    } else {
+                        format!("{}?{}", new_path, new_query)
+                    };
+                    return RewriteOutcome::Redirect { status, location };
+                }
+
+                let changed = new_path != path || new_query != query;
+                path = new_path;
+                query = new_query;
+
+                if rule.has(RewriteFlag::Break) || (rule.has(RewriteFlag::Last) && !changed) {
+                    break 'rounds;
+                }
+
+                if rule.has(RewriteFlag::Last) {
+                    if round == MAX_ROUNDS {
+                        println!("rewrite pravidla se zacyklila pro {}", input.path);
+                    }
+                    continue 'rounds;
+                }
+            }
+
+            break;
+        }
+
+        if path == input.path && query == input.query {
+            RewriteOutcome::Unchanged
+        } else {
+            RewriteOutcome::Rewrite { path, query }
+        }
+    }
+}
+
+fn compile_rule(rule: &RewriteRule) -> Result<CompiledRule, String> {
+    let pattern = compile_regex(&rule.pattern)?;
+
+    let conditions = rule.conditions
+        .iter()
+        .map(|condition| compile_condition(condition, &rule.pattern))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CompiledRule {
+        pattern,
+        target: rule.target.clone(),
+        flags: rule.flags.clone(),
+        conditions,
+    })
+}
+
+fn compile_condition(
+    condition: &RewriteCondition,
+    rule_pattern: &str
+) -> Result<CompiledCondition, String> {
+    let kind = match condition {
+        RewriteCondition { host: Some(host), .. } => ConditionKind::Host(compile_regex(host)?),
+        RewriteCondition { method: Some(methods), .. } =>
+            ConditionKind::Method(
+                methods
+                    .iter()
+                    .map(|m| m.to_ascii_uppercase())
+                    .collect()
+            ),
+        RewriteCondition { header: Some(name), value, .. } =>
+            ConditionKind::Header {
+                name: name.clone(),
+                value: value.as_deref().map(compile_regex).transpose()?,
+            },
+        RewriteCondition { file_exists: Some(path), root, .. } =>
+            ConditionKind::FileExists {
+                path: path.clone(),
+                root: root.clone(),
+            },
+        _ => {
+            return Err(format!("podminka pravidla {} nema zadny typ", rule_pattern));
+        }
+    };
+
+    Ok(CompiledCondition {
+        kind,
+        negate: condition.negate,
+    })
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("neplatny regex {}: {}", pattern, e))
+}
+
+impl CompiledRule {
+    fn has(&self, flag: RewriteFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    fn redirect_status(&self) -> Option<u16> {
+        self.flags.iter().find_map(|flag| {
+            match flag {
+                RewriteFlag::Redirect301 => Some(301),
+                RewriteFlag::Redirect302 => Some(302),
+                RewriteFlag::Redirect307 => Some(307),
+                RewriteFlag::Redirect308 => Some(308),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl CompiledCondition {
+    fn matches(&self, input: &RewriteInput, captures: &Captures) -> bool {
+        let result = match &self.kind {
+            ConditionKind::Host(pattern) => pattern.is_match(&input.host),
+            ConditionKind::Method(methods) => methods.contains(&input.method),
+            ConditionKind::Header { name, value } =>
+                match (input.header(name), value) {
+                    (Some(actual), Some(pattern)) => pattern.is_match(actual),
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                }
+            ConditionKind::FileExists { path: template, root } => {
+                let mut path = String::new();
+                captures.expand(template, &mut path);
+                let root = Path::new(root.as_deref().unwrap_or(&input.document_root));
+
+                // `$1` pochazi z URL - stejna pravidla jako u handleru, nic mimo koren
+                match resolve(root, &path) {
+                    Ok(resolved) => {
+                        resolved.full.is_file() && contain(root, &resolved.full).is_ok()
+                    }
+                    Err(_) => false,
+                }
+            }
+        };
+
+        result != self.negate
+    }
+}
+
+// `?` v cili oddeluje novy query string; bez nej zustava puvodni
+fn split_target(target: &str, original_query: &str, append_query: bool) -> (String, String) {
+    match target.split_once('?') {
+        Some((path, query)) => {
+            let query = if append_query && !original_query.is_empty() {
+                if query.is_empty() {
+                    original_query.to_string()
+                } else {
+                    format!("{}&{}", query, original_query)
+                }
+            } else {
+                query.to_string()
+            };
+            (path.to_string(), query)
+        }
+        None => (target.to_string(), original_query.to_string()),
+    }
+}
+
+/// middleware, ktery pred routovanim aplikuje `[[rewrite]]` pravidla
+#[derive(Clone)]
+pub struct Rewrite {
+    engine: Arc<RwLock<Arc<RewriteEngine>>>,
+}
+
+impl Rewrite {
+    /// pravidla se zkompiluji z konfigurace a znovu pri kazdem reloadu;
+    /// pri chybe zustava puvodni sada
+    pub fn new(config_manager: &ConfigManager) -> Self {
+        let engine = match RewriteEngine::compile(&config_manager.get_config().rewrites) {
+            Ok(engine) => engine,
+            Err(e) => {
+                println!("chyba v rewrite pravidlech, pravidla jsou vypnuta: {}", e);
+                RewriteEngine { rules: Vec::new() }
+            }
+        };
+
+        let engine = Arc::new(RwLock::new(Arc::new(engine)));
+
+        let reload_engine = engine.clone();
+        config_manager.on_reload(move |config| {
+            match RewriteEngine::compile(&config.rewrites) {
+                Ok(engine) => {
+                    *reload_engine.write().unwrap() = Arc::new(engine);
+                }
+                Err(e) => println!("chyba v rewrite pravidlech, zustavaji puvodni: {}", e),
+            }
+        });
+
+        Rewrite { engine }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest>
+    for Rewrite
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RewriteMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RewriteMiddleware {
+            service,
+            engine: self.engine.clone(),
+        })
+    }
+}
+
+pub struct RewriteMiddleware<S> {
+    service: S,
+    engine: Arc<RwLock<Arc<RewriteEngine>>>,
+}
+
+impl<S, B> Service<ServiceRequest>
+    for RewriteMiddleware<S>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: 'static
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let engine = self.engine.read().unwrap().clone();
+
+        if !engine.is_empty() {
+            match engine.evaluate(&RewriteInput::from_request(&req)) {
+                RewriteOutcome::Unchanged => {}
+                RewriteOutcome::Rewrite { path, query } => rewrite_uri(&mut req, &path, &query),
+                RewriteOutcome::Redirect { status, location } => {
+                    let response = HttpResponse::build(
+                        StatusCode::from_u16(status).unwrap_or(StatusCode::FOUND)
+                    )
+                        .insert_header((header::LOCATION, location))
+                        .finish();
+
+                    return Box::pin(async move {
+                        Ok(req.into_response(response).map_into_right_body())
+                    });
+                }
+            }
+        }
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move { fut.await.map(|res| res.map_into_left_body()) })
+    }
+}
+
+// zmeni URI requestu vcetne cesty, podle ktere actix routuje; puvodni URI
+// zustane v extensions jako `OriginalUri`
+fn rewrite_uri(req: &mut ServiceRequest, path: &str, query: &str) {
+    let path_and_query = if query.is_empty() { path.to_string() } else { format!("{}?{}", path, query) };
+
+    // scheme a authority (HTTP/2) zustavaji puvodni
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = match path_and_query.parse::<PathAndQuery>() {
+        Ok(path_and_query) => Some(path_and_query),
+        Err(_) => {
+            println!("neplatna URL po prepsani: {}", path_and_query);
+            return;
+        }
+    };
+
+    match Uri::from_parts(parts) {
+        Ok(uri) => {
+            let original = req
+                .uri()
+                .path_and_query()
+                .map(|pq| pq.as_str().to_string())
+                .unwrap_or_else(|| req.path().to_string());
+            req.extensions_mut().insert(OriginalUri(original));
+            req.match_info_mut().get_mut().update(&uri);
+            req.head_mut().uri = uri;
+        }
+        Err(e) => println!("neplatna URL po prepsani {}: {}", path_and_query, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::cgi_env::CgiRequestInfo;
+    use actix_web::test::TestRequest;
+
+    fn rule(pattern: &str, target: &str, flags: &[RewriteFlag]) -> RewriteRule {
+        RewriteRule {
+            pattern: pattern.to_string(),
+            target: target.to_string(),
+            flags: flags.to_vec(),
+            conditions: Vec::new(),
+        }
+    }
+
+    fn input(path: &str, query: &str) -> RewriteInput {
+        RewriteInput {
+            path: path.to_string(),
+            query: query.to_string(),
+            host: "example.com".to_string(),
+            method: "GET".to_string(),
+            ..RewriteInput::default()
+        }
+    }
+
+    fn evaluate(rules: Vec<RewriteRule>, input: &RewriteInput) -> RewriteOutcome {
+        RewriteEngine::compile(&rules).unwrap().evaluate(input)
+    }
+
+    fn rewritten(path: &str, query: &str) -> RewriteOutcome {
+        RewriteOutcome::Rewrite { path: path.to_string(), query: query.to_string() }
+    }
+
+    #[test]
+    fn substitutes_numbered_and_named_groups() {
+        let rules = vec![
+            rule(r"^/clanek/(\d+)/(?P<slug>[a-z-]+)$", "/php/clanek.php?id=$1&s=${slug}", &[])
+        ];
+
+        assert_eq!(
+            evaluate(rules, &input("/clanek/42/ahoj-svete", "")),
+            rewritten("/php/clanek.php", "id=42&s=ahoj-svete")
+        );
+    }
+
+    #[test]
+    fn qsa_appends_original_query() {
+        let with_query = || vec![rule("^/a$", "/b?x=1", &[RewriteFlag::Qsa])];
+        let empty_query = || vec![rule("^/a$", "/b?", &[RewriteFlag::Qsa])];
+
+        assert_eq!(evaluate(with_query(), &input("/a", "y=2")), rewritten("/b", "x=1&y=2"));
+        assert_eq!(evaluate(with_query(), &input("/a", "")), rewritten("/b", "x=1"));
+        assert_eq!(evaluate(empty_query(), &input("/a", "y=2")), rewritten("/b", "y=2"));
+        // bez qsa novy query string nahradi puvodni, bez `?` puvodni zustava
+        assert_eq!(
+            evaluate(vec![rule("^/a$", "/b?x=1", &[])], &input("/a", "y=2")),
+            rewritten("/b", "x=1")
+        );
+        assert_eq!(
+            evaluate(vec![rule("^/a$", "/b", &[])], &input("/a", "y=2")),
+            rewritten("/b", "y=2")
+        );
+    }
+
+    #[test]
+    fn last_restarts_and_break_stops() {
+        let rules = || vec![
+            rule("^/c$", "/d", &[]),
+            rule("^/a$", "/b", &[RewriteFlag::Last]),
+            rule("^/b$", "/c", &[])
+        ];
+        // /a -> /b (last), novy pruchod: /b -> /c az ve tretim pravidle, /c -> /d uz ne
+        assert_eq!(evaluate(rules(), &input("/a", "")), rewritten("/c", ""));
+
+        let rules = vec![
+            rule("^/a$", "/b", &[RewriteFlag::Break]),
+            rule("^/b$", "/c", &[])
+        ];
+        assert_eq!(evaluate(rules, &input("/a", "")), rewritten("/b", ""));
+
+        // bez priznaku pokracuje dalsim pravidlem v tomtez pruchodu
+        let rules = vec![rule("^/a$", "/b", &[]), rule("^/b$", "/c", &[])];
+        assert_eq!(evaluate(rules, &input("/a", "")), rewritten("/c", ""));
+    }
+
+    #[test]
+    fn last_loop_is_limited() {
+        // kazdy pruchod cestu zmeni, takze by `last` bez limitu nikdy neskoncil
+        let rules = vec![rule("^/(a*)$", "/a$1", &[RewriteFlag::Last])];
+
+        assert_eq!(
+            evaluate(rules, &input("/a", "")),
+            rewritten(&format!("/{}", "a".repeat(MAX_ROUNDS + 1)), "")
+        );
+    }
+
+    #[test]
+    fn redirect_flags_set_status_and_location() {
+        let cases = [
+            (RewriteFlag::Redirect301, 301),
+            (RewriteFlag::Redirect302, 302),
+            (RewriteFlag::Redirect307, 307),
+            (RewriteFlag::Redirect308, 308),
+        ];
+
+        for (flag, status) in cases {
+            let rules = vec![rule("^/stary/(.*)$", "/novy/$1", &[flag]), rule(".*", "/x", &[])];
+            assert_eq!(
+                evaluate(rules, &input("/stary/a/b", "q=1")),
+                RewriteOutcome::Redirect { status, location: "/novy/a/b?q=1".to_string() }
+            );
+        }
+    }
+
+    #[test]
+    fn conditions_must_all_match() {
+        let conditional = |conditions: Vec<RewriteCondition>| {
+            let mut rule = rule("^/a$", "/b", &[]);
+            rule.conditions = conditions;
+            vec![rule]
+        };
+        let host = |pattern: &str| RewriteCondition {
+            host: Some(pattern.to_string()),
+            ..RewriteCondition::default()
+        };
+        let method = |methods: &[&str]| RewriteCondition {
+            method: Some(methods.iter().map(|m| m.to_string()).collect()),
+            ..RewriteCondition::default()
+        };
+        let header = |name: &str, value: Option<&str>| RewriteCondition {
+            header: Some(name.to_string()),
+            value: value.map(str::to_string),
+            ..RewriteCondition::default()
+        };
+        let mut request = input("/a", "");
+        request.headers.push(("user-agent".to_string(), "Mozilla/5.0 Mobile".to_string()));
+
+        let applies = |conditions| {
+            evaluate(conditional(conditions), &request) != RewriteOutcome::Unchanged
+        };
+
+        assert!(applies(vec![host(r"^example\.com$")]));
+        assert!(!applies(vec![host(r"^www\.")]));
+        assert!(applies(vec![method(&["get", "HEAD"])]));
+        assert!(!applies(vec![method(&["POST"])]));
+        assert!(applies(vec![header("User-Agent", None)]));
+        assert!(applies(vec![header("User-Agent", Some("Mobile"))]));
+        assert!(!applies(vec![header("User-Agent", Some("^curl"))]));
+        assert!(!applies(vec![header("X-Missing", None)]));
+        assert!(!applies(vec![host("^example"), method(&["POST"])]));
+        assert!(applies(vec![RewriteCondition { negate: true, ..method(&["POST"]) }]));
+    }
+
+    #[test]
+    fn missing_condition_type_is_error() {
+        let mut rule = rule("^/a$", "/b", &[]);
+        rule.conditions.push(RewriteCondition::default());
+
+        assert!(RewriteEngine::compile(&[rule]).is_err());
+        assert!(RewriteEngine::compile(&[self::rule("(", "/b", &[])]).is_err());
+    }
+
+    #[test]
+    fn file_exists_stays_inside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("web");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("soubor.txt"), "x").unwrap();
+        std::fs::write(dir.path().join("tajne.txt"), "x").unwrap();
+
+        let mut rule = rule("^/(.*)$", "/index.php", &[]);
+        rule.conditions.push(RewriteCondition {
+            file_exists: Some("$1".to_string()),
+            negate: true,
+            ..RewriteCondition::default()
+        });
+        let engine = RewriteEngine::compile(&[rule]).unwrap();
+        let exists = |path: &str| {
+            let mut request = input(path, "");
+            request.document_root = root.to_string_lossy().to_string();
+            engine.evaluate(&request) == RewriteOutcome::Unchanged
+        };
+
+        assert!(exists("/soubor.txt"));
+        assert!(!exists("/neni.txt"));
+        // adresar neni soubor
+        assert!(!exists("/"));
+        assert!(!exists("/../tajne.txt"));
+        assert!(!exists("/%2e%2e/tajne.txt"));
+
+        #[cfg(unix)]
+        {
+            let outside = dir.path().join("tajne.txt");
+            std::os::unix::fs::symlink(outside, root.join("odkaz.txt")).unwrap();
+            assert!(!exists("/odkaz.txt"));
+        }
+    }
+
+    #[test]
+    fn file_exists_uses_own_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cache.html"), "x").unwrap();
+
+        let mut rule = rule("^/(.*)$", "/cache.html", &[]);
+        rule.conditions.push(RewriteCondition {
+            file_exists: Some("cache.html".to_string()),
+            root: Some(dir.path().to_string_lossy().to_string()),
+            ..RewriteCondition::default()
+        });
+
+        // document_root je prazdny, rozhoduje `root` podminky
+        assert_eq!(evaluate(vec![rule], &input("/a", "")), rewritten("/cache.html", ""));
+    }
+
+    #[test]
+    fn rewrite_keeps_original_request_uri() {
+        let mut req = TestRequest::default().uri("/blog/5?a=1").to_srv_request();
+        rewrite_uri(&mut req, "/index.php", "p=5");
+
+        assert_eq!(req.path(), "/index.php");
+        assert_eq!(req.query_string(), "p=5");
+        assert_eq!(CgiRequestInfo::from_request(req.request()).request_uri, "/blog/5?a=1");
+    }
+}
@@ -0,0 +1,269 @@
+use actix_web::http::header::{ HeaderName, HeaderValue };
+use actix_web::web::{ self, Bytes };
+use actix_web::HttpRequest;
+use notify::{ Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher };
+use serde::Serialize;
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::fs::{ self, Metadata };
+use std::path::{ Path, PathBuf };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::{ Arc, Mutex, Weak };
+use std::time::SystemTime;
+
+// jedna odpoved muze zabrat nejvys osminu cache, aby ji par velkych souboru nevyprazdnilo
+const MAX_ENTRY_SHARE: usize = 8;
+
+/// soubor, jeho verze (mtime a velikost) a varianta odpovedi (kodovani, minifikace,
+/// Cache-Control podle vhostu)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+    variant: String,
+}
+
+impl CacheKey {
+    pub fn for_file(path: &Path, metadata: &Metadata, variant: String) -> Option<Self> {
+        Some(CacheKey {
+            path: fs::canonicalize(path).ok()?,
+            modified: metadata.modified().ok()?,
+            size: metadata.len(),
+            variant,
+        })
+    }
+}
+
+/// hotova odpoved 200 - telo po minifikaci a kompresi a jeji hlavicky
+pub struct CachedResponse {
+    pub body: Bytes,
+    pub etag: String,
+    pub last_modified: String,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl CachedResponse {
+    // odhad obsazene pameti vcetne hlavicek
+    fn weight(&self) -> usize {
+        let headers: usize = self.headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+
+        self.body.len() + self.etag.len() + self.last_modified.len() + headers
+    }
+}
+
+#[derive(Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub size: usize,
+    pub capacity: usize,
+}
+
+/// LRU cache hotovych odpovedi s limitem pameti, sdilena workery; zaznamy
+/// zmenenych souboru maze watcher
+pub struct ResponseCache {
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+struct CacheState {
+    capacity: usize,
+    size: usize,
+    tick: u64,
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// poradi pouziti - nejstarsi zaznam je prvni
+    order: BTreeMap<u64, CacheKey>,
+    watched_dirs: HashSet<PathBuf>,
+}
+
+struct CacheEntry {
+    response: Arc<CachedResponse>,
+    tick: u64,
+    weight: usize,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new_cyclic(|cache: &Weak<ResponseCache>| {
+            let cache = cache.clone();
+            let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+                let event = match res {
+                    Ok(event) => event,
+                    Err(e) => {
+                        println!("chyba watcheru cache: {}", e);
+                        return;
+                    }
+                };
+
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+
+                if let Some(cache) = cache.upgrade() {
+                    cache.invalidate(&event.paths);
+                }
+            });
+
+            let watcher = match watcher {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    println!("chyba pri vytvareni watcheru cache, zmeny pozname jen podle mtime: {}", e);
+                    None
+                }
+            };
+
+            ResponseCache {
+                state: Mutex::new(CacheState {
+                    capacity,
+                    size: 0,
+                    tick: 0,
+                    entries: HashMap::new(),
+                    order: BTreeMap::new(),
+                    watched_dirs: HashSet::new(),
+                }),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                watcher: Mutex::new(watcher),
+            }
+        })
+    }
+
+    /// cache sdilena aplikaci, pokud je zaregistrovana
+    pub fn of(req: &HttpRequest) -> Option<Arc<ResponseCache>> {
+        req.app_data::<web::Data<Arc<ResponseCache>>>().map(|cache| cache.get_ref().clone())
+    }
+
+    /// jestli se odpoved s telem dane velikosti vubec muze do cache vejit
+    pub fn accepts(&self, size: u64) -> bool {
+        let capacity = self.state.lock().unwrap().capacity;
+        capacity > 0 && size <= ((capacity / MAX_ENTRY_SHARE) as u64)
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<CachedResponse>> {
+        let mut state = self.state.lock().unwrap();
+        if state.capacity == 0 {
+            return None;
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+
+        let found = state.entries.get_mut(key).map(|entry| {
+            let previous = entry.tick;
+            entry.tick = tick;
+            (previous, entry.response.clone())
+        });
+
+        match found {
+            Some((previous, response)) => {
+                state.order.remove(&previous);
+                state.order.insert(tick, key.clone());
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(response)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: CacheKey, response: Arc<CachedResponse>) {
+        let weight = response.weight();
+        let dir = key.path.parent().map(Path::to_path_buf);
+
+        let new_dir = {
+            let mut state = self.state.lock().unwrap();
+            if state.capacity == 0 || weight > state.capacity / MAX_ENTRY_SHARE {
+                return;
+            }
+
+            state.remove(&key);
+            state.tick += 1;
+            let tick = state.tick;
+
+            state.size += weight;
+            state.order.insert(tick, key.clone());
+            state.entries.insert(key, CacheEntry { response, tick, weight });
+            state.evict();
+
+            dir.filter(|dir| state.watched_dirs.insert(dir.clone()))
+        };
+
+        // watch se nastavuje mimo zamek - watcher muze zrovna cekat na invalidate
+        if let Some(dir) = new_dir {
+            if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+                if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                    println!("cache nemuze sledovat {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+
+    /// odstrani vsechny varianty zmenenych souboru
+    pub fn invalidate(&self, paths: &[PathBuf]) {
+        let mut state = self.state.lock().unwrap();
+
+        let keys: Vec<CacheKey> = state.entries
+            .keys()
+            .filter(|key| paths.contains(&key.path))
+            .cloned()
+            .collect();
+
+        for key in keys {
+            state.remove(&key);
+        }
+    }
+
+    /// zmena limitu po reloadu konfigurace; 0 cache vypne a vyprazdni
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.capacity = capacity;
+        state.evict();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+
+        CacheStats {
+            enabled: state.capacity > 0,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: state.entries.len(),
+            size: state.size,
+            capacity: state.capacity,
+        }
+    }
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.size -= entry.weight;
+        }
+    }
+
+    // vyhazuje nejdele nepouzite zaznamy, dokud se cache nevejde do limitu
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            let key = match self.order.pop_first() {
+                Some((_, key)) => key,
+                None => {
+                    break;
+                }
+            };
+
+            if let Some(entry) = self.entries.remove(&key) {
+                self.size -= entry.weight;
+            }
+        }
+    }
+}
@@ -1,117 +1,270 @@
-use actix_web::{ web, App, HttpServer, HttpResponse, Responder };
+use actix_web::{ web, App, HttpServer, HttpMessage, HttpRequest, HttpResponse };
+use actix_web::http::{ header, Method, StatusCode };
 use std::sync::Arc;
-use crate::config::{ ConfigManager };
+use crate::config::{ ConfigManager, Location, LocationHandler, MatchedLocation };
+use crate::utils::strip_port;
+use crate::utils::path_resolver::resolve;
+use crate::middleware::logger::Logger;
+use crate::middleware::rewrite::Rewrite;
+use crate::middleware::compress::Compress;
+use crate::server::cache::ResponseCache;
+use crate::server::routes::Routes;
+use crate::server::tls::{ build_server_config, CertResolver };
+use crate::server::upstream::UpstreamRegistry;
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::rt::net::TcpStream;
 use crate::handlers::{
     static_files::serve_static_file,
-    php_handler::handle_php,
+    php_handler::{ handle_php, serve_php_target },
     javascript_handler::handle_js,
     get_config,
     update_config,
     reload_config,
+    get_upstreams,
+    get_cache_stats,
+    handle_proxy,
+    websocket_handler,
 };
-use std::process::{ Command, Stdio };
-use std::io::Read;
+use crate::handlers::proxy_handler::proxy_client;
+use crate::handlers::static_files::serve_file;
+use crate::handlers::cgi_env::{ directory_index, is_php, resolve_script, try_files, PhpTarget };
 use std::path::Path;
-use std::time::Duration;
-use wait_timeout::ChildExt;
 
 pub async fn start_http_server(config_manager: Arc<ConfigManager>) -> std::io::Result<()> {
     // inicializujeme config manager a nacteme konfiguraci
     let config = config_manager.get_config();
+    let app_config_manager = config_manager.clone();
 
-    HttpServer::new(move || {
+    // upstream skupiny pro proxy - stav je sdileny vsemi workery
+    let upstreams = Arc::new(UpstreamRegistry::new(&config));
+    let reload_upstreams = upstreams.clone();
+    config_manager.on_reload(move |config| reload_upstreams.sync(config));
+    upstreams.clone().spawn_health_checks();
+
+    // rewrite pravidla bezi pred routovanim, Logger vypisuje puvodni URL
+    let rewrite = Rewrite::new(&config_manager);
+
+    // cache hotovych odpovedi statickych a JS souboru - limit lze zmenit reloadem
+    let response_cache = ResponseCache::new(config.response_cache_size);
+    let reload_cache = response_cache.clone();
+    config_manager.on_reload(move |config| reload_cache.set_capacity(config.response_cache_size));
+
+    // tabulka [[location]] - pri chybe v konfiguraci server nenastartuje
+    let routes = Routes::new(&config_manager).map_err(to_io_error)?;
+
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(config_manager.clone()))
-            .route("/", web::get().to(index))
-            .route("/static/{filename:.*}", web::get().to(serve_static_file))
-            .route("/php/{filename:.*}", web::to(handle_php))
-            .route("/js/{filename:.*}", web::get().to(handle_js))
+            .wrap(Compress)
+            .wrap(rewrite.clone())
+            .wrap(Logger::new())
+            .app_data(web::Data::new(app_config_manager.clone()))
+            .app_data(web::Data::new(upstreams.clone()))
+            .app_data(web::Data::new(proxy_client()))
+            .app_data(web::Data::new(routes.clone()))
+            .app_data(web::Data::new(response_cache.clone()))
             // pridame endpointy pro spravu konfigurace
             .route("/api/config", web::get().to(get_config))
             .route("/api/config", web::post().to(update_config))
             .route("/api/config/reload", web::post().to(reload_config))
-            .default_service(web::route().to(handle_404))
+            .route("/api/upstreams", web::get().to(get_upstreams))
+            .route("/api/cache", web::get().to(get_cache_stats))
+            // vse ostatni jde pres tabulku location
+            .default_service(web::to(dispatch))
+    });
+
+    let plain_address = format!("{}:{}", config.bind_address, config.port);
+
+    if !config.tls.enabled {
+        return server.bind(plain_address)?.run().await;
+    }
+
+    let resolver = Arc::new(CertResolver::new(&config.tls).map_err(to_io_error)?);
+
+    // certifikaty se nacitaji znovu pri zmene configu nebo samotnych souboru
+    let reload_resolver = resolver.clone();
+    config_manager.on_reload(move |config| {
+        if !config.tls.enabled {
+            return;
+        }
+        match reload_resolver.reload(&config.tls) {
+            Ok(_) => println!("TLS certifikaty byly znovu nacteny"),
+            Err(e) => println!("chyba pri nacteni TLS certifikatu, zustava puvodni: {}", e),
+        }
+    });
+
+    // do dat spojeni si ulozime, ktery certifikat byl podle SNI vybran (vypisuje Logger)
+    let connect_resolver = resolver.clone();
+    let server = server.on_connect(move |conn, extensions| {
+        if let Some(stream) = conn.downcast_ref::<TlsStream<TcpStream>>() {
+            let (_, session) = stream.get_ref();
+            extensions.insert(connect_resolver.describe(session.server_name()));
+        }
+    });
+
+    let tls_config = build_server_config(&config.tls, resolver).map_err(to_io_error)?;
+    let tls_port = config.tls.port.unwrap_or(config.port);
+    let tls_address = format!("{}:{}", config.bind_address, tls_port);
+
+    println!("zapinam HTTPS na {}", tls_address);
+
+    let server = server.bind_rustls_0_23(tls_address, tls_config)?;
+
+    // bez samostatneho TLS portu bezi na `port` pouze HTTPS
+    if tls_port == config.port {
+        return server.run().await;
+    }
+
+    if config.tls.redirect_http {
+        let redirect = start_redirect_server(&plain_address, tls_port)?;
+        futures::future::try_join(server.run(), redirect).await.map(|_| ())
+    } else {
+        server.bind(plain_address)?.run().await
+    }
+}
+
+// HTTP listener, ktery vse presmeruje na HTTPS
+fn start_redirect_server(
+    address: &str,
+    tls_port: u16
+) -> std::io::Result<actix_web::dev::Server> {
+    let server = HttpServer::new(move || {
+        App::new().default_service(
+            web::to(move |req: HttpRequest| redirect_to_https(req, tls_port))
+        )
     })
-        .bind(format!("{}:{}", config.bind_address, config.port))?
-        .run().await
+        .bind(address)?
+        .run();
+
+    Ok(server)
 }
 
-async fn handle_404(config_manager: web::Data<Arc<ConfigManager>>) -> impl Responder {
-    let config = config_manager.get_config();
+async fn redirect_to_https(req: HttpRequest, tls_port: u16) -> HttpResponse {
+    let host = req.connection_info().host().to_string();
+    let host = strip_port(&host);
 
-    if config.custom_error_pages {
-        if let Some(error_page_path) = config.error_pages.get("404") {
-            let full_path = std::path::Path::new(&config.static_root).join(error_page_path);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
 
-            if full_path.exists() && full_path.is_file() {
-                if let Ok(content) = std::fs::read_to_string(&full_path) {
-                    return HttpResponse::NotFound().content_type("text/html").body(content);
-                }
-            }
+    let location = if tls_port == 443 {
+        format!("https://{}{}", host, path)
+    } else {
+        format!("https://{}:{}{}", host, tls_port, path)
+    };
+
+    HttpResponse::MovedPermanently().insert_header((header::LOCATION, location)).finish()
+}
+
+fn to_io_error(e: String) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+// vybere location podle cesty a preda request jejimu handleru
+async fn dispatch(
+    req: HttpRequest,
+    payload: web::Payload,
+    config_manager: web::Data<Arc<ConfigManager>>,
+    upstreams: web::Data<Arc<UpstreamRegistry>>,
+    client: web::Data<reqwest::Client>,
+    routes: web::Data<Routes>
+) -> HttpResponse {
+    let matched = match routes.find(req.path()) {
+        Some(matched) => matched,
+        None if req.path() == "/" => {
+            return index(req, payload, config_manager).await;
+        }
+        None => {
+            return handle_404(req, payload, config_manager).await;
+        }
+    };
+
+    req.extensions_mut().insert(matched.clone());
+    let location = matched.location.clone();
+
+    match location.handler {
+        LocationHandler::Static | LocationHandler::Js if !is_read_method(&req) => {
+            HttpResponse::MethodNotAllowed().insert_header((header::ALLOW, "GET, HEAD")).finish()
         }
+        LocationHandler::Static => serve_static_file(req, config_manager).await,
+        LocationHandler::Js => handle_js(req, config_manager).await,
+        LocationHandler::Php => handle_php(req, payload, config_manager).await,
+        LocationHandler::Proxy => {
+            handle_proxy(req, payload, config_manager, upstreams, client).await
+        }
+        LocationHandler::Websocket => {
+            websocket_handler(req, payload, config_manager)
+                .await
+                .unwrap_or_else(|e| e.error_response())
+        }
+        LocationHandler::Redirect => redirect_location(&location, &matched),
+        LocationHandler::Return => return_location(&location),
     }
+}
 
-    // kdyz custom 404 neni nacten nebo neexistuje, vracime internal server 404 template
-    return HttpResponse::NotFound()
-        .content_type("text/html")
-        .body(
-            r#"
-                <!DOCTYPE html>
-                <html>
-                <head>
-                    <title>404 Not Found</title>
-                </head>
-                <body>
-                    <center><h1>404 Not Found</h1></center>
-                    <hr>
-                    <center>Qlira/0.1.1</center>
-                </body>
-                </html>
-            "#
-        );
+fn is_read_method(req: &HttpRequest) -> bool {
+    req.method() == Method::GET || req.method() == Method::HEAD
 }
 
-async fn index(config_manager: web::Data<Arc<ConfigManager>>) -> impl Responder {
-    let config = config_manager.get_config();
-    let server_dir = std::path::Path::new(&config.server_directory);
-
-    let index_files = ["index.php", "index.html"];
-    for file in &index_files {
-        let file_path = server_dir.join(file);
-        if file_path.exists() && file_path.is_file() {
-            if file == &"index.php" {
-                if let Ok(content) = std::fs::read_to_string(&file_path) {
-                    // pokud zacina <?php, spustime PHP
-                    // jinak vracime HTML jako text
-                    if content.trim_start().starts_with("<?php") && config.php_enabled {
-                        match
-                            execute_php_file(
-                                &file_path,
-                                &config.php_exe_path,
-                                config.php_timeout
-                            ).await
-                        {
-                            Ok(output) => {
-                                return HttpResponse::Ok().content_type("text/html").body(output);
-                            }
-                            Err(e) => {
-                                println!("chyba pri zpracovani php souboru: {}", e);
-                            }
-                        }
-                    }
-                    // pokud PHP neni povoleno nebo soubor neobsahuje PHP, vracime jako text
-                    return HttpResponse::Ok().content_type("text/html").body(content);
-                }
-            } else if let Ok(content) = std::fs::read_to_string(&file_path) {
-                let content_type = mime_guess::from_path(file).first_or_octet_stream();
-                return HttpResponse::Ok().content_type(content_type.to_string()).body(content);
-            }
+// `$1`..`$9` v cili nahradime skupinami z regexu location
+fn redirect_location(location: &Location, matched: &MatchedLocation) -> HttpResponse {
+    let mut target = location.target.clone();
+    for (index, capture) in matched.captures.iter().enumerate().rev() {
+        target = target.replace(&format!("${}", index), capture.as_deref().unwrap_or(""));
+    }
+
+    let status = location.status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .filter(|status| status.is_redirection())
+        .unwrap_or(StatusCode::FOUND);
+
+    HttpResponse::build(status).insert_header((header::LOCATION, target)).finish()
+}
+
+fn return_location(location: &Location) -> HttpResponse {
+    let status = location.status
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+
+    HttpResponse::build(status)
+        .content_type(location.content_type.as_deref().unwrap_or("text/plain; charset=utf-8"))
+        .body(location.body.clone())
+}
+
+async fn handle_404(
+    req: HttpRequest,
+    payload: web::Payload,
+    config_manager: web::Data<Arc<ConfigManager>>
+) -> HttpResponse {
+    let config = config_manager.get_request_config(&req);
+
+    // front controller v rootu webu (Laravel, Symfony, WordPress) - try_files v php_root_dir
+    if config.php_enabled {
+        // neplatna cesta front controller nedostane, skonci jako 404
+        let relative = resolve(Path::new(&config.php_root_dir), req.path()).ok();
+        let target = config.php_front_controller
+            .as_deref()
+            .zip(relative)
+            .and_then(|(front_controller, relative)| {
+                try_files(
+                    Path::new(&config.php_root_dir),
+                    "",
+                    &relative.relative,
+                    front_controller,
+                    &config.index_files
+                )
+            });
+
+        if let Some(target) = target {
+            return serve_php_target(req, payload, config, target).await;
         }
     }
 
     if config.custom_error_pages {
         if let Some(error_page_path) = config.error_pages.get("404") {
             let full_path = std::path::Path::new(&config.static_root).join(error_page_path);
+
             if full_path.exists() && full_path.is_file() {
                 if let Ok(content) = std::fs::read_to_string(&full_path) {
                     return HttpResponse::NotFound().content_type("text/html").body(content);
@@ -120,6 +273,7 @@ async fn index(config_manager: web::Data<Arc<ConfigManager>>) -> impl Responder
         }
     }
 
+    // kdyz custom 404 neni nacten nebo neexistuje, vracime internal server 404 template
     HttpResponse::NotFound()
         .content_type("text/html")
         .body(
@@ -139,45 +293,99 @@ async fn index(config_manager: web::Data<Arc<ConfigManager>>) -> impl Responder
         )
 }
 
-// pomocna funkce pro spusteni PHP souboru
-async fn execute_php_file(
-    file_path: &Path,
-    php_exe_path: &str,
-    timeout_seconds: u64
-) -> Result<String, String> {
-    let mut command = Command::new(php_exe_path);
-
-    command.arg(file_path);
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
-
-    let mut child = command.spawn().map_err(|e| format!("chyba pri spusteni PHP: {}", e))?;
-
-    let timeout = Duration::from_secs(timeout_seconds);
-    match child.wait_timeout(timeout) {
-        Ok(status_opt) => {
-            match status_opt {
-                Some(status) => {
-                    if !status.success() {
-                        return Err(format!("chyba pri spusteni PHP: {}", status));
-                    }
-                }
-                None => {
-                    child.kill().ok();
-                    return Err("chyba: PHP proces prekrocil timeout".to_string());
-                }
+// index korene webu (server_directory) podle `index_files`; PHP jde stejnou cestou
+// jako ostatni PHP requesty, jinak 404 vcetne front controlleru
+async fn index(
+    req: HttpRequest,
+    payload: web::Payload,
+    config_manager: web::Data<Arc<ConfigManager>>
+) -> HttpResponse {
+    let config = config_manager.get_request_config(&req);
+    let server_dir = Path::new(&config.server_directory);
+
+    // bez PHP se PHP index preskoci, aby se neposlal zdrojak
+    let index_files: Vec<String> = config.index_files
+        .iter()
+        .filter(|name| config.php_enabled || !is_php(Path::new(name)))
+        .cloned()
+        .collect();
+
+    if let Some(name) = directory_index(server_dir, &index_files) {
+        let file_path = server_dir.join(name);
+
+        if !is_php(&file_path) {
+            if !is_read_method(&req) {
+                return HttpResponse::MethodNotAllowed()
+                    .insert_header((header::ALLOW, "GET, HEAD"))
+                    .finish();
             }
+            return serve_file(&req, &config, server_dir, &file_path);
         }
-        Err(e) => {
-            return Err(format!("chyba pri cekani na PHP proces: {}", e));
+
+        if let Some(script) = resolve_script(server_dir, "", name, &index_files) {
+            return serve_php_target(req, payload, config, PhpTarget::Script(script)).await;
         }
     }
 
-    let mut output = String::new();
-    child.stdout
-        .unwrap()
-        .read_to_string(&mut output)
-        .map_err(|e| format!("chyba pri cteni vystupu PHP: {}", e))?;
+    handle_404(req, payload, config_manager).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use actix_web::test as actix_test;
 
-    Ok(output)
+    // server_directory s index.php a index.html
+    fn config_manager(dir: &Path, php_enabled: bool, index_files: &[&str]) -> Arc<ConfigManager> {
+        let server_dir = dir.join("www");
+        std::fs::create_dir_all(&server_dir).unwrap();
+        std::fs::write(server_dir.join("index.php"), "<?php echo 'php';").unwrap();
+        std::fs::write(server_dir.join("index.html"), "html").unwrap();
+
+        let path = dir.join("server.toml");
+        let path = path.to_str().unwrap();
+        ServerConfig {
+            server_directory: server_dir.to_str().unwrap().to_string(),
+            php_enabled,
+            index_files: index_files.iter().map(|name| name.to_string()).collect(),
+            ..ServerConfig::default()
+        }.save(path).unwrap();
+        Arc::new(ConfigManager::new(path).unwrap())
+    }
+
+    async fn get_index(config_manager: Arc<ConfigManager>) -> (StatusCode, String) {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(config_manager))
+                .route("/", web::to(index))
+        ).await;
+
+        let response = actix_test::call_service(&app, actix_test::TestRequest::get().to_request())
+            .await;
+        let status = response.status();
+        let body = actix_test::read_body(response).await;
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn php_index_is_skipped_without_php() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let manager = config_manager(dir.path(), false, &["index.php", "index.html"]);
+        assert_eq!(get_index(manager).await, (StatusCode::OK, "html".to_string()));
+
+        // jen PHP index - zdrojak se neposle, 404
+        let dir = tempfile::tempdir().unwrap();
+        let (status, body) = get_index(config_manager(dir.path(), false, &["index.php"])).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(!body.contains("<?php"));
+    }
+
+    #[actix_web::test]
+    async fn index_files_are_tried_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = config_manager(dir.path(), false, &["missing.html", "index.html"]);
+        assert_eq!(get_index(manager).await, (StatusCode::OK, "html".to_string()));
+    }
 }
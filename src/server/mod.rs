@@ -1 +1,5 @@
+pub mod cache;
 pub mod http;
+pub mod routes;
+pub mod tls;
+pub mod upstream;
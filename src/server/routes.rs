@@ -0,0 +1,235 @@
+use regex::Regex;
+use std::cmp::Reverse;
+use std::sync::{ Arc, RwLock };
+use crate::config::{
+    ConfigManager,
+    Location,
+    LocationHandler,
+    LocationMatch,
+    MatchedLocation,
+    ServerConfig,
+};
+use crate::utils::path_resolver::prefix_matches;
+
+/// zkompilovana tabulka `[[location]]` vcetne vychozich cest
+pub struct RouteTable {
+    exact: Vec<Arc<Location>>,
+    regex: Vec<(Regex, Arc<Location>)>,
+    /// serazene od nejdelsiho prefixu
+    prefix: Vec<Arc<Location>>,
+}
+
+impl RouteTable {
+    pub fn build(config: &ServerConfig) -> Result<Self, String> {
+        let mut locations = default_locations(config);
+
+        // location se stejnou cestou a typem shody nahradi predchozi (i vychozi)
+        for location in &config.locations {
+            locations.retain(|l| !(l.path == location.path && l.match_kind == location.match_kind));
+            locations.push(location.clone());
+        }
+
+        let mut table = RouteTable {
+            exact: Vec::new(),
+            regex: Vec::new(),
+            prefix: Vec::new(),
+        };
+
+        for location in locations {
+            match location.match_kind {
+                LocationMatch::Exact => table.exact.push(Arc::new(location)),
+                LocationMatch::Regex => {
+                    let pattern = Regex::new(&location.path).map_err(|e|
+                        format!("neplatny regex location {}: {}", location.path, e)
+                    )?;
+                    table.regex.push((pattern, Arc::new(location)));
+                }
+                LocationMatch::Prefix => table.prefix.push(Arc::new(location)),
+            }
+        }
+
+        table.prefix.sort_by_key(|l| Reverse(l.path.len()));
+
+        Ok(table)
+    }
+
+    /// presna shoda, potom prvni odpovidajici regex, potom nejdelsi prefix
+    pub fn find(&self, path: &str) -> Option<MatchedLocation> {
+        if let Some(location) = self.exact.iter().find(|l| l.path == path) {
+            return Some(matched(location, "", path, Vec::new()));
+        }
+
+        for (pattern, location) in &self.regex {
+            if let Some(captures) = pattern.captures(path) {
+                // skupina `path` urcuje cestu v koreni location, jinak cela cesta
+                let relative = captures.name("path").map_or(path, |m| m.as_str());
+                let url_prefix = path.strip_suffix(relative).unwrap_or("");
+                let captures = captures
+                    .iter()
+                    .map(|c| c.map(|c| c.as_str().to_string()))
+                    .collect();
+
+                return Some(matched(location, url_prefix, relative, captures));
+            }
+        }
+
+        self.prefix
+            .iter()
+            .find(|l| prefix_matches(&l.path, path))
+            .map(|location| {
+                let relative = &path[location.path.len()..];
+                matched(location, &location.path, relative, Vec::new())
+            })
+    }
+}
+
+fn matched(
+    location: &Arc<Location>,
+    url_prefix: &str,
+    relative_path: &str,
+    captures: Vec<Option<String>>
+) -> MatchedLocation {
+    MatchedLocation {
+        location: location.clone(),
+        url_prefix: url_prefix.to_string(),
+        relative_path: relative_path.trim_start_matches('/').to_string(),
+        captures,
+    }
+}
+
+// cesty, ktere server obsluhoval vzdy - zustavaji, dokud je location neprepise
+fn default_locations(config: &ServerConfig) -> Vec<Location> {
+    let prefix = |path: &str, handler: LocationHandler| Location {
+        path: path.to_string(),
+        handler,
+        ..Location::default()
+    };
+
+    let mut locations = vec![
+        prefix("/static/", LocationHandler::Static),
+        prefix("/php/", LocationHandler::Php),
+        prefix("/js/", LocationHandler::Js)
+    ];
+
+    for rule in &config.proxies {
+        locations.push(Location {
+            upstream: rule.upstream.clone(),
+            strip_prefix: rule.strip_prefix,
+            timeout: rule.timeout,
+            ..prefix(&rule.prefix, LocationHandler::Proxy)
+        });
+    }
+
+    if config.enable_websockets {
+        locations.push(Location {
+            match_kind: LocationMatch::Exact,
+            ..prefix(&config.websocket_path, LocationHandler::Websocket)
+        });
+    }
+
+    locations
+}
+
+/// tabulka sdilena workery, po reloadu konfigurace se sestavi znovu
+#[derive(Clone)]
+pub struct Routes {
+    table: Arc<RwLock<Arc<RouteTable>>>,
+}
+
+impl Routes {
+    pub fn new(config_manager: &ConfigManager) -> Result<Self, String> {
+        let table = RouteTable::build(&config_manager.get_config())?;
+        let table = Arc::new(RwLock::new(Arc::new(table)));
+
+        let reload_table = table.clone();
+        config_manager.on_reload(move |config| {
+            match RouteTable::build(config) {
+                Ok(table) => {
+                    *reload_table.write().unwrap() = Arc::new(table);
+                }
+                Err(e) => println!("chyba v [[location]], zustava puvodni tabulka: {}", e),
+            }
+        });
+
+        Ok(Routes { table })
+    }
+
+    pub fn find(&self, path: &str) -> Option<MatchedLocation> {
+        let table = self.table.read().unwrap().clone();
+        table.find(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(path: &str, match_kind: LocationMatch, handler: LocationHandler) -> Location {
+        Location {
+            path: path.to_string(),
+            match_kind,
+            handler,
+            ..Location::default()
+        }
+    }
+
+    fn table(locations: Vec<Location>) -> RouteTable {
+        let config = ServerConfig {
+            locations,
+            ..ServerConfig::default()
+        };
+        RouteTable::build(&config).unwrap()
+    }
+
+    fn found(table: &RouteTable, path: &str) -> Option<(String, String)> {
+        table
+            .find(path)
+            .map(|m| (m.location.path.clone(), m.relative_path.clone()))
+    }
+
+    fn pair(path: &str, relative: &str) -> Option<(String, String)> {
+        Some((path.to_string(), relative.to_string()))
+    }
+
+    #[test]
+    fn prefix_ends_at_segment_boundary() {
+        let table = table(vec![
+            location("/api", LocationMatch::Prefix, LocationHandler::Proxy),
+            location("/", LocationMatch::Prefix, LocationHandler::Static)
+        ]);
+
+        assert_eq!(found(&table, "/api"), pair("/api", ""));
+        assert_eq!(found(&table, "/api/users"), pair("/api", "users"));
+        assert_eq!(found(&table, "/apix"), pair("/", "apix"));
+        assert_eq!(found(&table, "/api-docs/a"), pair("/", "api-docs/a"));
+    }
+
+    #[test]
+    fn prefix_with_trailing_slash_matches_anything_below() {
+        let table = table(vec![location("/img/", LocationMatch::Prefix, LocationHandler::Static)]);
+
+        assert_eq!(found(&table, "/img/a.png"), pair("/img/", "a.png"));
+        assert_eq!(found(&table, "/img/"), pair("/img/", ""));
+        // vychozi `/static/` neodpovida `/static`, jen cestam pod nim
+        assert_eq!(found(&table, "/static/a.css"), pair("/static/", "a.css"));
+        assert_eq!(found(&table, "/static"), None);
+        assert_eq!(found(&table, "/staticx"), None);
+    }
+
+    #[test]
+    fn exact_then_regex_then_longest_prefix() {
+        let table = table(vec![
+            location("/app", LocationMatch::Prefix, LocationHandler::Static),
+            location("/app/api", LocationMatch::Prefix, LocationHandler::Proxy),
+            location(r"^/app/(?P<path>.*\.php)$", LocationMatch::Regex, LocationHandler::Php),
+            location("/app/api/health", LocationMatch::Exact, LocationHandler::Return)
+        ]);
+        let handler = |path: &str| table.find(path).map(|m| m.location.handler);
+
+        assert_eq!(handler("/app/api/health"), Some(LocationHandler::Return));
+        assert_eq!(handler("/app/api/index.php"), Some(LocationHandler::Php));
+        assert_eq!(handler("/app/api/users"), Some(LocationHandler::Proxy));
+        assert_eq!(handler("/app/apiary"), Some(LocationHandler::Static));
+        assert_eq!(handler("/application"), None);
+    }
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{ Arc, RwLock };
+use rustls::crypto::ring::{ default_provider, sign::any_supported_type };
+use rustls::pki_types::{ CertificateDer, PrivateKeyDer };
+use rustls::server::{ ClientHello, ResolvesServerCert };
+use rustls::sign::CertifiedKey;
+use rustls::version::{ TLS12, TLS13 };
+use rustls::SupportedProtocolVersion;
+use crate::config::TlsConfig;
+use crate::utils::wildcard_matches;
+
+// resolver certifikatu podle SNI, jehoz obsah jde vymenit za behu (hot reload)
+pub struct CertResolver {
+    store: RwLock<Arc<CertStore>>,
+}
+
+// informace o vybranem certifikatu ulozena do dat spojeni (pro request log)
+#[derive(Debug, Clone)]
+pub struct TlsCertInfo {
+    pub server_name: Option<String>,
+    pub certificate: String,
+}
+
+struct NamedCert {
+    /// nazev pro log - prvni server name nebo "default"
+    label: String,
+    cert_path: String,
+    key_path: String,
+    server_names: Vec<String>,
+    key: Arc<CertifiedKey>,
+}
+
+impl NamedCert {
+    // pro log - nazev a soubor, aby byla poznat i vymena certifikatu pod stejnym nazvem
+    fn identity(&self) -> String {
+        format!("{} ({})", self.label, self.cert_path)
+    }
+}
+
+struct CertStore {
+    default: Arc<NamedCert>,
+    named: Vec<Arc<NamedCert>>,
+}
+
+impl CertStore {
+    fn load(tls: &TlsConfig, previous: Option<&CertStore>) -> Result<Self, String> {
+        let default = Arc::new(NamedCert {
+            label: "default".to_string(),
+            cert_path: tls.cert_path.clone(),
+            key_path: tls.key_path.clone(),
+            server_names: Vec::new(),
+            key: load_certified_key(&tls.cert_path, &tls.key_path)?,
+        });
+
+        let mut named = Vec::new();
+
+        for entry in &tls.certificates {
+            let label = entry.server_names
+                .first()
+                .cloned()
+                .unwrap_or_else(|| entry.cert_path.clone());
+
+            match load_certified_key(&entry.cert_path, &entry.key_path) {
+                Ok(key) => {
+                    named.push(
+                        Arc::new(NamedCert {
+                            label,
+                            cert_path: entry.cert_path.clone(),
+                            key_path: entry.key_path.clone(),
+                            server_names: normalize_names(&entry.server_names),
+                            key,
+                        })
+                    );
+                }
+                Err(e) => {
+                    // pri chybe (napr. rozepsana obnova certifikatu) nechame puvodni verzi,
+                    // jinak by host docasne dostaval vychozi certifikat
+                    let old = previous.and_then(|store| {
+                        store.named
+                            .iter()
+                            .find(|c| c.cert_path == entry.cert_path && c.key_path == entry.key_path)
+                    });
+
+                    match old {
+                        Some(old) => {
+                            println!("chyba pri nacteni certifikatu {}, zustava puvodni: {}", label, e);
+                            named.push(
+                                Arc::new(NamedCert {
+                                    label,
+                                    cert_path: old.cert_path.clone(),
+                                    key_path: old.key_path.clone(),
+                                    server_names: normalize_names(&entry.server_names),
+                                    key: old.key.clone(),
+                                })
+                            );
+                        }
+                        None => println!("certifikat {} preskocen: {}", label, e),
+                    }
+                }
+            }
+        }
+
+        Ok(CertStore { default, named })
+    }
+
+    // presna shoda ma prednost pred wildcardem, jinak vychozi certifikat
+    fn select(&self, server_name: Option<&str>) -> &Arc<NamedCert> {
+        let name = match server_name {
+            Some(name) => name.trim_end_matches('.').to_ascii_lowercase(),
+            None => {
+                return &self.default;
+            }
+        };
+
+        if let Some(cert) = self.named.iter().find(|c| c.server_names.contains(&name)) {
+            return cert;
+        }
+
+        self.named
+            .iter()
+            .find(|c| c.server_names.iter().any(|n| wildcard_matches(n, &name)))
+            .unwrap_or(&self.default)
+    }
+}
+
+fn normalize_names(names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .map(|n| n.trim().trim_end_matches('.').to_ascii_lowercase())
+        .collect()
+}
+
+impl CertResolver {
+    pub fn new(tls: &TlsConfig) -> Result<Self, String> {
+        let store = CertStore::load(tls, None)?;
+
+        Ok(CertResolver {
+            store: RwLock::new(Arc::new(store)),
+        })
+    }
+
+    /// nacte certifikaty znovu; probihajici spojeni si drzi svuj puvodni klic,
+    /// pri chybe vychoziho certifikatu zustava aktivni puvodni sada
+    pub fn reload(&self, tls: &TlsConfig) -> Result<(), String> {
+        let previous = self.store.read().unwrap().clone();
+        let store = CertStore::load(tls, Some(&previous))?;
+        *self.store.write().unwrap() = Arc::new(store);
+        Ok(())
+    }
+
+    /// certifikat pro SNI ulozene v rustls session spojeni - stejny vyber jako
+    /// v `resolve()`, takze nezavisi na tom, na kterem vlakne handshake bezel
+    pub fn describe(&self, server_name: Option<&str>) -> TlsCertInfo {
+        let store = self.store.read().unwrap().clone();
+
+        TlsCertInfo {
+            server_name: server_name.map(|s| s.to_string()),
+            certificate: store.select(server_name).identity(),
+        }
+    }
+}
+
+impl fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertResolver").finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let store = self.store.read().unwrap().clone();
+        Some(store.select(client_hello.server_name()).key.clone())
+    }
+}
+
+pub fn build_server_config(
+    tls: &TlsConfig,
+    resolver: Arc<CertResolver>
+) -> Result<rustls::ServerConfig, String> {
+    let versions = protocol_versions(&tls.min_version)?;
+
+    // ALPN (h2, http/1.1) doplnuje actix pri bindovani
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_protocol_versions(versions)
+        .map_err(|e| format!("chyba pri nastaveni TLS verzi: {}", e))?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    Ok(config)
+}
+
+fn protocol_versions(
+    min_version: &str
+) -> Result<&'static [&'static SupportedProtocolVersion], String> {
+    static TLS12_AND_UP: [&SupportedProtocolVersion; 2] = [&TLS13, &TLS12];
+    static TLS13_ONLY: [&SupportedProtocolVersion; 1] = [&TLS13];
+
+    match min_version {
+        "1.2" => Ok(&TLS12_AND_UP),
+        "1.3" => Ok(&TLS13_ONLY),
+        _ => Err(format!("nepodporovana minimalni verze TLS: {}", min_version)),
+    }
+}
+
+pub fn load_certified_key(cert_path: &str, key_path: &str) -> Result<Arc<CertifiedKey>, String> {
+    let certs = load_certs(cert_path)?;
+    let key = load_private_key(key_path)?;
+
+    let signing_key = any_supported_type(&key).map_err(|e|
+        format!("nepodporovany privatni klic {}: {}", key_path, e)
+    )?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+
+    // pri vymene souboru muze byt chvili novy certifikat a stary klic
+    certified
+        .keys_match()
+        .map_err(|e| format!("certifikat {} neodpovida klici {}: {}", cert_path, key_path, e))?;
+
+    Ok(Arc::new(certified))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e|
+        format!("chyba pri otevirani certifikatu {}: {}", path, e)
+    )?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("chyba pri cteni certifikatu {}: {}", path, e))?;
+
+    if certs.is_empty() {
+        return Err(format!("soubor {} neobsahuje zadny certifikat", path));
+    }
+
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    let file = File::open(path).map_err(|e| format!("chyba pri otevirani klice {}: {}", path, e))?;
+
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("chyba pri cteni klice {}: {}", path, e))?
+        .ok_or_else(|| format!("soubor {} neobsahuje privatni klic", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::sign::{ Signer, SigningKey };
+    use rustls::{ SignatureAlgorithm, SignatureScheme };
+
+    // vyber certifikatu klic nepouziva, staci atrapa
+    #[derive(Debug)]
+    struct DummyKey;
+
+    impl SigningKey for DummyKey {
+        fn choose_scheme(&self, _offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+            None
+        }
+
+        fn algorithm(&self) -> SignatureAlgorithm {
+            SignatureAlgorithm::ECDSA
+        }
+    }
+
+    fn cert(label: &str, names: &[&str]) -> Arc<NamedCert> {
+        Arc::new(NamedCert {
+            label: label.to_string(),
+            cert_path: format!("{}.pem", label),
+            key_path: format!("{}.key", label),
+            server_names: normalize_names(
+                &names.iter().map(|n| n.to_string()).collect::<Vec<_>>()
+            ),
+            key: Arc::new(CertifiedKey::new(Vec::new(), Arc::new(DummyKey))),
+        })
+    }
+
+    fn resolver() -> CertResolver {
+        CertResolver {
+            store: RwLock::new(
+                Arc::new(CertStore {
+                    default: cert("default", &[]),
+                    named: vec![
+                        cert("wildcard", &["*.example.com"]),
+                        cert("www", &["WWW.example.com."])
+                    ],
+                })
+            ),
+        }
+    }
+
+    #[test]
+    fn describe_follows_sni_selection() {
+        let resolver = resolver();
+        let certificate = |name| resolver.describe(name).certificate;
+
+        assert_eq!(certificate(Some("www.example.com")), "www (www.pem)");
+        assert_eq!(certificate(Some("WWW.Example.com.")), "www (www.pem)");
+        assert_eq!(certificate(Some("api.example.com")), "wildcard (wildcard.pem)");
+        assert_eq!(certificate(Some("a.b.example.com")), "default (default.pem)");
+        assert_eq!(certificate(None), "default (default.pem)");
+        let described = resolver.describe(Some("api.example.com"));
+        assert_eq!(described.server_name.as_deref(), Some("api.example.com"));
+    }
+}
@@ -0,0 +1,437 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{ Hash, Hasher };
+use std::sync::atomic::{ AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering };
+use std::sync::{ Arc, Mutex, RwLock };
+use std::time::{ Duration, Instant };
+use crate::config::{ BalanceStrategy, ServerConfig, UpstreamGroup };
+
+const HEALTH_CHECK_TICK: Duration = Duration::from_secs(1);
+
+/// stav vsech `[[upstream]]` skupin sdileny mezi workery
+pub struct UpstreamRegistry {
+    groups: RwLock<HashMap<String, Arc<GroupState>>>,
+}
+
+struct GroupState {
+    config: UpstreamGroup,
+    peers: Vec<Arc<PeerState>>,
+    next: AtomicUsize,
+}
+
+pub struct PeerState {
+    url: String,
+    active: AtomicUsize,
+    consecutive_fails: AtomicU32,
+    total_requests: AtomicU64,
+    total_failures: AtomicU64,
+    /// vysledek posledniho aktivniho health checku
+    healthy: AtomicBool,
+    ejected_until: Mutex<Option<Instant>>,
+    last_check: Mutex<Option<Instant>>,
+    /// health check prave bezi - pomaly server nedostane dalsi, dokud neskonci
+    checking: AtomicBool,
+}
+
+impl PeerState {
+    fn new(url: &str) -> Self {
+        PeerState {
+            url: url.trim_end_matches('/').to_string(),
+            active: AtomicUsize::new(0),
+            consecutive_fails: AtomicU32::new(0),
+            total_requests: AtomicU64::new(0),
+            total_failures: AtomicU64::new(0),
+            healthy: AtomicBool::new(true),
+            ejected_until: Mutex::new(None),
+            last_check: Mutex::new(None),
+            checking: AtomicBool::new(false),
+        }
+    }
+
+    fn is_available(&self, now: Instant) -> bool {
+        if !self.healthy.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match *self.ejected_until.lock().unwrap() {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+}
+
+/// vybrany server; drzi pocitadlo aktivnich spojeni dokud neni zahozen
+pub struct PeerGuard {
+    peer: Arc<PeerState>,
+    max_fails: u32,
+    fail_timeout: Duration,
+    fail_statuses: Vec<u16>,
+}
+
+impl PeerGuard {
+    pub fn url(&self) -> &str {
+        &self.peer.url
+    }
+
+    pub fn report_success(&self) {
+        self.peer.consecutive_fails.store(0, Ordering::Relaxed);
+    }
+
+    /// odpoved upstreamu - kody z `fail_statuses` (502, 503...) se pocitaji jako chyba
+    pub fn report_status(&self, status: u16) {
+        if self.fail_statuses.contains(&status) {
+            self.report_failure();
+        } else {
+            self.report_success();
+        }
+    }
+
+    // pasivni kontrola - po `max_fails` chybach za sebou server vyradime na `fail_timeout`
+    pub fn report_failure(&self) {
+        self.peer.total_failures.fetch_add(1, Ordering::Relaxed);
+        let fails = self.peer.consecutive_fails.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if self.max_fails > 0 && fails >= self.max_fails {
+            self.peer.consecutive_fails.store(0, Ordering::Relaxed);
+            *self.peer.ejected_until.lock().unwrap() = Some(Instant::now() + self.fail_timeout);
+            println!("upstream {} vyrazen po {} chybach za sebou", self.peer.url, fails);
+        }
+    }
+}
+
+impl Drop for PeerGuard {
+    fn drop(&mut self) {
+        self.peer.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+pub struct GroupStatus {
+    pub name: String,
+    pub strategy: BalanceStrategy,
+    pub servers: Vec<PeerStatus>,
+}
+
+#[derive(Serialize)]
+pub struct PeerStatus {
+    pub url: String,
+    pub available: bool,
+    pub healthy: bool,
+    /// zbyvajici doba vyrazeni (v sekundach)
+    pub ejected_for: Option<u64>,
+    pub active_connections: usize,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+}
+
+impl UpstreamRegistry {
+    pub fn new(config: &ServerConfig) -> Self {
+        let registry = UpstreamRegistry {
+            groups: RwLock::new(HashMap::new()),
+        };
+        registry.sync(config);
+        registry
+    }
+
+    /// prestavi skupiny podle konfigurace; stav serveru, ktere zustaly, se zachova
+    pub fn sync(&self, config: &ServerConfig) {
+        let mut groups = self.groups.write().unwrap();
+        let mut new_groups = HashMap::new();
+
+        for group in &config.upstreams {
+            let old = groups.get(&group.name);
+
+            let peers = group.servers
+                .iter()
+                .map(|url| {
+                    let url = url.trim_end_matches('/');
+                    old.and_then(|old| old.peers.iter().find(|p| p.url == url))
+                        .cloned()
+                        .unwrap_or_else(|| Arc::new(PeerState::new(url)))
+                })
+                .collect();
+
+            new_groups.insert(
+                group.name.clone(),
+                Arc::new(GroupState {
+                    config: group.clone(),
+                    peers,
+                    next: AtomicUsize::new(0),
+                })
+            );
+        }
+
+        *groups = new_groups;
+    }
+
+    /// vybere server ze skupiny podle strategie; kdyz jsou vsechny nedostupne,
+    /// zkusime i vyrazene (lepsi nez rovnou vratit chybu)
+    pub fn select(&self, name: &str, client_ip: &str) -> Option<PeerGuard> {
+        let group = self.groups.read().unwrap().get(name)?.clone();
+
+        if group.peers.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut candidates: Vec<&Arc<PeerState>> = group.peers
+            .iter()
+            .filter(|p| p.is_available(now))
+            .collect();
+
+        if candidates.is_empty() {
+            candidates = group.peers.iter().collect();
+        }
+
+        let peer = match group.config.strategy {
+            BalanceStrategy::RoundRobin => {
+                let index = group.next.fetch_add(1, Ordering::Relaxed);
+                candidates[index % candidates.len()]
+            }
+            BalanceStrategy::LeastConnections => {
+                candidates
+                    .iter()
+                    .min_by_key(|p| p.active.load(Ordering::Relaxed))
+                    .copied()
+                    .unwrap()
+            }
+            BalanceStrategy::IpHash => {
+                // hash pres vsechny servery, aby klient zustal na stejnem serveru,
+                // i kdyz jiny vypadne; nedostupny server preskocime na dalsi
+                let mut hasher = DefaultHasher::new();
+                client_ip.hash(&mut hasher);
+                let start = (hasher.finish() as usize) % group.peers.len();
+
+                (0..group.peers.len())
+                    .map(|offset| &group.peers[(start + offset) % group.peers.len()])
+                    .find(|p| candidates.iter().any(|c| Arc::ptr_eq(c, p)))
+                    .unwrap_or(candidates[0])
+            }
+        };
+
+        peer.active.fetch_add(1, Ordering::Relaxed);
+        peer.total_requests.fetch_add(1, Ordering::Relaxed);
+
+        Some(PeerGuard {
+            peer: peer.clone(),
+            max_fails: group.config.max_fails,
+            fail_timeout: Duration::from_secs(group.config.fail_timeout),
+            fail_statuses: group.config.fail_statuses.clone(),
+        })
+    }
+
+    pub fn status(&self) -> Vec<GroupStatus> {
+        let groups = self.groups.read().unwrap();
+        let now = Instant::now();
+
+        let mut status: Vec<GroupStatus> = groups
+            .values()
+            .map(|group| GroupStatus {
+                name: group.config.name.clone(),
+                strategy: group.config.strategy,
+                servers: group.peers
+                    .iter()
+                    .map(|peer| PeerStatus {
+                        url: peer.url.clone(),
+                        available: peer.is_available(now),
+                        healthy: peer.healthy.load(Ordering::Relaxed),
+                        ejected_for: peer.ejected_until
+                            .lock()
+                            .unwrap()
+                            .filter(|until| *until > now)
+                            .map(|until| (until - now).as_secs()),
+                        active_connections: peer.active.load(Ordering::Relaxed),
+                        consecutive_failures: peer.consecutive_fails.load(Ordering::Relaxed),
+                        total_requests: peer.total_requests.load(Ordering::Relaxed),
+                        total_failures: peer.total_failures.load(Ordering::Relaxed),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        status.sort_by(|a, b| a.name.cmp(&b.name));
+        status
+    }
+
+    // servery, kterym uz vyprsel interval aktivniho health checku
+    fn due_health_checks(&self) -> Vec<(Arc<PeerState>, String, Duration)> {
+        let groups = self.groups.read().unwrap();
+        let now = Instant::now();
+        let mut due = Vec::new();
+
+        for group in groups.values() {
+            let path = match &group.config.health_check_path {
+                Some(path) => path,
+                None => {
+                    continue;
+                }
+            };
+            let interval = Duration::from_secs(group.config.health_check_interval.max(1));
+
+            for peer in &group.peers {
+                if peer.checking.load(Ordering::Relaxed) {
+                    continue;
+                }
+
+                let mut last_check = peer.last_check.lock().unwrap();
+                if last_check.is_some_and(|last| now.duration_since(last) < interval) {
+                    continue;
+                }
+                *last_check = Some(now);
+
+                let url = format!("{}/{}", peer.url, path.trim_start_matches('/'));
+                due.push((peer.clone(), url, Duration::from_secs(group.config.health_check_timeout)));
+            }
+        }
+
+        due
+    }
+
+    /// spusti na pozadi aktivni health checky vsech skupin; kazda kontrola bezi
+    /// jako samostatny task, takze zaseknuty server nezdrzi kontroly ostatnich
+    pub fn spawn_health_checks(self: Arc<Self>) {
+        actix_web::rt::spawn(async move {
+            let client = reqwest::Client::new();
+
+            loop {
+                actix_web::rt::time::sleep(HEALTH_CHECK_TICK).await;
+
+                for (peer, url, timeout) in self.due_health_checks() {
+                    peer.checking.store(true, Ordering::Relaxed);
+                    let request = client.get(url).timeout(timeout).send();
+
+                    actix_web::rt::spawn(async move {
+                        let healthy = match request.await {
+                            Ok(response) =>
+                                response.status().is_success() ||
+                                    response.status().is_redirection(),
+                            Err(_) => false,
+                        };
+
+                        let was_healthy = peer.healthy.swap(healthy, Ordering::Relaxed);
+                        if was_healthy != healthy {
+                            println!(
+                                "upstream {} je {}",
+                                peer.url,
+                                if healthy { "znovu dostupny" } else { "nedostupny (health check)" }
+                            );
+                        }
+
+                        peer.checking.store(false, Ordering::Relaxed);
+                    });
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{ web, App, HttpResponse, HttpServer };
+
+    fn registry(group: UpstreamGroup) -> UpstreamRegistry {
+        UpstreamRegistry::new(
+            &(ServerConfig {
+                upstreams: vec![group],
+                ..ServerConfig::default()
+            })
+        )
+    }
+
+    fn group(servers: &[&str]) -> UpstreamGroup {
+        UpstreamGroup {
+            name: "app".to_string(),
+            servers: servers.iter().map(|s| s.to_string()).collect(),
+            max_fails: 2,
+            ..UpstreamGroup::default()
+        }
+    }
+
+    #[test]
+    fn fail_statuses_eject_peer() {
+        let registry = registry(group(&["http://a", "http://b"]));
+
+        // a vraci 503, b 404 - 404 je odpoved aplikace, ne chyba serveru
+        for _ in 0..4 {
+            let peer = registry.select("app", "").unwrap();
+            let status = if peer.url() == "http://a" { 503 } else { 404 };
+            peer.report_status(status);
+        }
+
+        for _ in 0..4 {
+            assert_eq!(registry.select("app", "").unwrap().url(), "http://b");
+        }
+
+        let status = registry.status();
+        let a = &status[0].servers[0];
+        assert!(!a.available);
+        assert_eq!(a.total_failures, 2);
+        assert_eq!(status[0].servers[1].total_failures, 0);
+    }
+
+    #[test]
+    fn success_resets_consecutive_failures() {
+        let registry = registry(group(&["http://a"]));
+
+        for status in [502, 200, 502, 200] {
+            registry.select("app", "").unwrap().report_status(status);
+        }
+
+        let status = registry.status();
+        assert!(status[0].servers[0].available);
+        assert_eq!(status[0].servers[0].consecutive_failures, 0);
+    }
+
+    #[actix_web::test]
+    async fn hung_health_check_does_not_delay_others() {
+        // server, ktery spojeni prijme, ale nikdy neodpovi
+        let hung = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let hung_address = hung.local_addr().unwrap();
+
+        // druhy server projde jen prvni kontrolou, zmenu pozna az dalsi kolo
+        let probes = Arc::new(AtomicUsize::new(0));
+        let failing = HttpServer::new(move || {
+            let probes = probes.clone();
+            App::new().default_service(
+                web::to(move || {
+                    let first = probes.fetch_add(1, Ordering::Relaxed) == 0;
+                    async move {
+                        if first {
+                            HttpResponse::Ok().finish()
+                        } else {
+                            HttpResponse::ServiceUnavailable().finish()
+                        }
+                    }
+                })
+            )
+        })
+            .workers(1)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let failing_address = failing.addrs()[0];
+        actix_web::rt::spawn(failing.run());
+
+        let registry = Arc::new(
+            registry(UpstreamGroup {
+                health_check_path: Some("/health".to_string()),
+                health_check_interval: 1,
+                health_check_timeout: 30,
+                ..group(
+                    &[&format!("http://{}", hung_address), &format!("http://{}", failing_address)]
+                )
+            })
+        );
+        registry.clone().spawn_health_checks();
+
+        // dalsi kola kontrol bezi, i kdyz prvni server stale visi
+        actix_web::rt::time::sleep(Duration::from_millis(3500)).await;
+
+        let status = registry.status();
+        assert!(status[0].servers[0].healthy);
+        assert!(!status[0].servers[1].healthy);
+        drop(hung);
+    }
+}
@@ -0,0 +1,369 @@
+use brotli::CompressorWriter;
+use flate2::write::{ DeflateEncoder, GzEncoder };
+use flate2::Compression;
+use serde::{ Deserialize, Serialize };
+use std::io::{ self, Read, Write };
+
+/// content coding odpovedi
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[serde(rename = "br")]
+    Brotli,
+    Zstd,
+    Gzip,
+    Deflate,
+    Identity,
+}
+
+impl Encoding {
+    /// hodnota pro Accept-Encoding / Content-Encoding
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Identity => "identity",
+        }
+    }
+
+    /// pripona predkomprimovaneho souboru (`foo.css.br`)
+    pub fn sidecar_extension(&self) -> Option<&'static str> {
+        match self {
+            Encoding::Brotli => Some("br"),
+            Encoding::Zstd => Some("zst"),
+            Encoding::Gzip => Some("gz"),
+            Encoding::Deflate | Encoding::Identity => None,
+        }
+    }
+}
+
+// od urovne 20 pouziva zstd okno vetsi nez 8 MB, ktere prohlizece dekoduji
+const MAX_ZSTD_LEVEL: i32 = 19;
+
+/// `[compression_levels]` - uroven komprese pro kazdy kodek
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionLevels {
+    /// 0-11
+    pub br: u32,
+    /// 1-19 (vyssi se snizi na 19)
+    pub zstd: i32,
+    /// 0-9
+    pub gzip: u32,
+    /// 0-9
+    pub deflate: u32,
+}
+
+impl Default for CompressionLevels {
+    fn default() -> Self {
+        CompressionLevels {
+            br: 4,
+            zstd: 3,
+            gzip: 6,
+            deflate: 6,
+        }
+    }
+}
+
+/// vychozi poradi kodeku, kdyz klient prijima vic z nich se stejnou vahou
+pub fn default_preference() -> Vec<Encoding> {
+    vec![Encoding::Brotli, Encoding::Zstd, Encoding::Gzip, Encoding::Deflate]
+}
+
+/// prijatelna kodovani podle Accept-Encoding (RFC 9110, 12.5.3) serazena od
+/// nejvyssi vahy; pri stejne vaze rozhoduje poradi serveru, `identity` je posledni.
+/// Kodeky mimo `preference` server nepouziva.
+pub fn ranked(accept_encoding: Option<&str>, preference: &[Encoding]) -> Vec<Encoding> {
+    // bez hlavicky posilame nekomprimovane
+    let accept_encoding = match accept_encoding {
+        Some(value) => value,
+        None => {
+            return vec![Encoding::Identity];
+        }
+    };
+
+    let mut weights: Vec<(String, f32)> = Vec::new();
+    for item in accept_encoding.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        if coding.is_empty() {
+            continue;
+        }
+
+        let mut weight = Some(1.0);
+        for param in parts {
+            if let Some((name, value)) = param.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("q") {
+                    weight = parse_weight(value.trim());
+                }
+            }
+        }
+
+        // polozku s neplatnou vahou ignorujeme
+        if let Some(weight) = weight {
+            weights.push((coding, weight));
+        }
+    }
+
+    let weight_of = |coding: &str| -> Option<f32> {
+        weights
+            .iter()
+            .find(|(name, _)| name == coding)
+            .or_else(|| weights.iter().find(|(name, _)| name == "*"))
+            .map(|(_, weight)| *weight)
+    };
+
+    let mut candidates: Vec<(Encoding, f32)> = preference
+        .iter()
+        .filter(|encoding| **encoding != Encoding::Identity)
+        .map(|encoding| (*encoding, weight_of(encoding.as_str()).unwrap_or(0.0)))
+        .collect();
+
+    // `x-gzip` je podle RFC totez co gzip
+    for (encoding, weight) in candidates.iter_mut() {
+        if *encoding == Encoding::Gzip && *weight == 0.0 {
+            if let Some((_, w)) = weights.iter().find(|(name, _)| name == "x-gzip") {
+                *weight = *w;
+            }
+        }
+    }
+
+    // identity je prijatelne vzdy, pokud ho klient vyslovne (nebo pres `*`) nevyloucil;
+    // bez uvedene vahy az jako posledni moznost
+    candidates.push((Encoding::Identity, weight_of("identity").unwrap_or(f32::MIN_POSITIVE)));
+
+    candidates.retain(|(_, weight)| *weight > 0.0);
+    // stabilni razeni zachova poradi serveru pro stejne vahy
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    candidates.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// nejlepsi kodovani pro odpoved; kdyz klient nic neprijima, posle se nekomprimovane
+pub fn negotiate(accept_encoding: Option<&str>, preference: &[Encoding]) -> Encoding {
+    ranked(accept_encoding, preference).first().copied().unwrap_or(Encoding::Identity)
+}
+
+/// q = 0 az 1 s nejvys tremi desetinnymi misty (RFC 9110, 12.4.2)
+pub fn parse_weight(value: &str) -> Option<f32> {
+    let weight = value.parse::<f32>().ok()?;
+    if (0.0..=1.0).contains(&weight) && value.len() <= 5 {
+        Some(weight)
+    } else {
+        None
+    }
+}
+
+/// zkomprimuje vse z `reader` do `writer` a vrati ho
+pub fn compress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: W,
+    encoding: Encoding,
+    levels: &CompressionLevels
+) -> Result<W, io::Error> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut encoder = CompressorWriter::new(writer, 4096, levels.br.min(11), 22);
+            io::copy(reader, &mut encoder)?;
+            encoder.flush()?;
+            Ok(encoder.into_inner())
+        }
+        Encoding::Zstd => {
+            let level = levels.zstd.min(MAX_ZSTD_LEVEL);
+            let mut encoder = zstd::stream::write::Encoder::new(writer, level)?;
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()
+        }
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(writer, Compression::new(levels.gzip.min(9)));
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()
+        }
+        Encoding::Deflate => {
+            let mut encoder = DeflateEncoder::new(writer, Compression::new(levels.deflate.min(9)));
+            io::copy(reader, &mut encoder)?;
+            encoder.finish()
+        }
+        Encoding::Identity => {
+            let mut writer = writer;
+            io::copy(reader, &mut writer)?;
+            Ok(writer)
+        }
+    }
+}
+
+/// kompresor pro telo, ktere prichazi po castech (PHP, proxy); po kazde casti
+/// se vystup flushne, aby streamovane odpovedi nezustaly viset v bufferu
+pub struct StreamEncoder {
+    inner: EncoderKind,
+}
+
+enum EncoderKind {
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl StreamEncoder {
+    /// None pro `identity`
+    pub fn new(encoding: Encoding, levels: &CompressionLevels) -> Result<Option<Self>, io::Error> {
+        let inner = match encoding {
+            Encoding::Brotli => {
+                EncoderKind::Brotli(
+                    Box::new(CompressorWriter::new(Vec::new(), 4096, levels.br.min(11), 22))
+                )
+            }
+            Encoding::Zstd => {
+                let level = levels.zstd.min(MAX_ZSTD_LEVEL);
+                EncoderKind::Zstd(zstd::stream::write::Encoder::new(Vec::new(), level)?)
+            }
+            Encoding::Gzip => {
+                EncoderKind::Gzip(GzEncoder::new(Vec::new(), Compression::new(levels.gzip.min(9))))
+            }
+            Encoding::Deflate => {
+                EncoderKind::Deflate(
+                    DeflateEncoder::new(Vec::new(), Compression::new(levels.deflate.min(9)))
+                )
+            }
+            Encoding::Identity => {
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(StreamEncoder { inner }))
+    }
+
+    /// zkomprimuje dalsi cast a vrati vystup, ktery je k dispozici
+    pub fn write(&mut self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        match &mut self.inner {
+            EncoderKind::Brotli(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            EncoderKind::Zstd(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            EncoderKind::Gzip(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            EncoderKind::Deflate(encoder) => {
+                encoder.write_all(data)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    /// ukonci stream a vrati zbytek vystupu
+    pub fn finish(self) -> Result<Vec<u8>, io::Error> {
+        match self.inner {
+            EncoderKind::Brotli(encoder) => Ok(encoder.into_inner()),
+            EncoderKind::Zstd(encoder) => encoder.finish(),
+            EncoderKind::Gzip(encoder) => encoder.finish(),
+            EncoderKind::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// typy obsahu, u kterych ma komprese smysl
+pub fn is_compressible(content_type: &str) -> bool {
+    let compressible_types = [
+        "text/",
+        "application/javascript",
+        "application/json",
+        "application/xml",
+        "application/xhtml+xml",
+        "image/svg+xml",
+        "application/wasm",
+    ];
+
+    compressible_types.iter().any(|&t| content_type.starts_with(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encoding::*;
+
+    fn ranked_default(accept_encoding: &str) -> Vec<Encoding> {
+        ranked(Some(accept_encoding), &default_preference())
+    }
+
+    #[test]
+    fn zero_weight_excludes_coding() {
+        assert_eq!(ranked_default("br;q=0, gzip"), [Gzip, Identity]);
+        assert_eq!(ranked_default("gzip;q=0, *"), [Brotli, Zstd, Deflate, Identity]);
+    }
+
+    #[test]
+    fn wildcard_zero_with_identity() {
+        // `*;q=0` vylouci vse, co neni vyslovne uvedene, vcetne identity
+        assert_eq!(ranked_default("*;q=0"), []);
+        assert_eq!(ranked_default("*;q=0, identity"), [Identity]);
+        assert_eq!(ranked_default("*;q=0, gzip;q=0.5"), [Gzip]);
+        assert_eq!(negotiate(Some("*;q=0"), &default_preference()), Identity);
+    }
+
+    #[test]
+    fn empty_or_missing_header_means_identity() {
+        assert_eq!(ranked_default(""), [Identity]);
+        assert_eq!(ranked(None, &default_preference()), [Identity]);
+    }
+
+    #[test]
+    fn malformed_weight_is_ignored() {
+        assert_eq!(parse_weight("1.5"), None);
+        assert_eq!(parse_weight("0.0001"), None);
+        assert_eq!(parse_weight("abc"), None);
+        assert_eq!(parse_weight("0.001"), Some(0.001));
+        assert_eq!(parse_weight("1"), Some(1.0));
+
+        assert_eq!(ranked_default("br;q=1.5, gzip"), [Gzip, Identity]);
+        assert_eq!(ranked_default("br;q=0.0001, gzip;q=0.5"), [Gzip, Identity]);
+    }
+
+    #[test]
+    fn weight_wins_over_preference() {
+        assert_eq!(ranked_default("gzip, br;q=0.5"), [Gzip, Brotli, Identity]);
+        assert_eq!(ranked_default("br;q=0.1, identity;q=0.5"), [Identity, Brotli]);
+    }
+
+    #[test]
+    fn ties_follow_server_preference() {
+        assert_eq!(ranked_default("gzip, deflate, br"), [Brotli, Gzip, Deflate, Identity]);
+        assert_eq!(ranked_default("x-gzip"), [Gzip, Identity]);
+        // neznamy kodek se ignoruje
+        assert_eq!(ranked_default("compress, gzip"), [Gzip, Identity]);
+    }
+
+    #[test]
+    fn preference_is_configurable() {
+        let preference = [Gzip, Brotli];
+
+        assert_eq!(ranked(Some("br, gzip, zstd"), &preference), [Gzip, Brotli, Identity]);
+        assert_eq!(negotiate(Some("zstd"), &preference), Identity);
+    }
+
+    #[test]
+    fn zstd_level_is_capped() {
+        let levels = CompressionLevels { zstd: 22, ..CompressionLevels::default() };
+        let data = b"abc".repeat(1000);
+
+        let compressed = compress(&mut &data[..], Vec::new(), Zstd, &levels).unwrap();
+
+        // prohlizece dekoduji okno nejvys 8 MB (window_log 23)
+        let mut decoder = zstd::stream::read::Decoder::new(&compressed[..]).unwrap();
+        decoder.window_log_max(23).unwrap();
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
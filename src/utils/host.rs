@@ -0,0 +1,54 @@
+// odstrani port z Host hlavicky (vcetne IPv6 tvaru [::1]:8080)
+pub fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => &host[..=end],
+            None => host,
+        };
+    }
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+/// `*.example.com` odpovida presne jednomu labelu navic (`a.example.com`, ne `a.b.example.com`
+/// ani `example.com`) - stejne jako certifikaty podle RFC 6125, vhost tak nikdy nedostane
+/// host, ktery jeho wildcard certifikat nepokryva
+pub fn wildcard_matches(pattern: &str, host: &str) -> bool {
+    let suffix = match pattern.strip_prefix("*.") {
+        Some(suffix) => suffix,
+        None => {
+            return false;
+        }
+    };
+
+    match host.split_once('.') {
+        Some((label, rest)) => !label.is_empty() && rest.eq_ignore_ascii_case(suffix),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_matches_single_label() {
+        assert!(wildcard_matches("*.example.com", "a.example.com"));
+        assert!(wildcard_matches("*.Example.com", "a.example.com"));
+        assert!(!wildcard_matches("*.example.com", "a.b.example.com"));
+        assert!(!wildcard_matches("*.example.com", "example.com"));
+        assert!(!wildcard_matches("*.example.com", ".example.com"));
+        assert!(!wildcard_matches("*.example.com", "aexample.com"));
+        assert!(!wildcard_matches("example.com", "example.com"));
+    }
+
+    #[test]
+    fn strip_port_keeps_ipv6_host() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+    }
+}
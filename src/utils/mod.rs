@@ -1,3 +1,7 @@
 pub mod performance;
+pub mod host;
+pub mod compression;
+pub mod path_resolver;
 
 pub use performance::*;
+pub use host::*;
@@ -0,0 +1,262 @@
+use percent_encoding::percent_decode_str;
+use std::fmt;
+use std::fs;
+use std::path::{ Component, Path, PathBuf };
+
+/// proc cesta z URL nemuze vest do korene
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// po dekodovani neni UTF-8
+    InvalidEncoding,
+    NullByte,
+    /// segment `..`
+    Traversal,
+    /// `C:` a podobne prefixy Windows
+    DrivePrefix,
+    /// kanonicka cesta lezi mimo koren
+    OutsideRoot,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            PathError::InvalidEncoding => "cesta neni platne UTF-8",
+            PathError::NullByte => "nulovy bajt v ceste",
+            PathError::Traversal => "segment .. v ceste",
+            PathError::DrivePrefix => "prefix disku v ceste",
+            PathError::OutsideRoot => "cesta mimo koren",
+        };
+        f.write_str(message)
+    }
+}
+
+/// cesta uvnitr korene
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedPath {
+    /// dekodovana cesta relativne ke koreni, segmenty oddelene `/`, bez `.` a prazdnych
+    pub relative: String,
+    /// koren + relativni cesta
+    pub full: PathBuf,
+}
+
+/// prevede cast URL za prefixem location (jeste nedekodovanou) na cestu v koreni;
+/// stejne pravidla pro static, JS i PHP. `\` je oddelovac jako `/`.
+pub fn resolve(root: &Path, raw_path: &str) -> Result<ResolvedPath, PathError> {
+    let decoded = percent_decode_str(raw_path)
+        .decode_utf8()
+        .map_err(|_| PathError::InvalidEncoding)?;
+
+    if decoded.contains('\0') {
+        return Err(PathError::NullByte);
+    }
+
+    let decoded = decoded.replace('\\', "/");
+    let mut segments = Vec::new();
+
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                return Err(PathError::Traversal);
+            }
+            segment if is_drive_prefix(segment) => {
+                return Err(PathError::DrivePrefix);
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let relative = segments.join("/");
+
+    // pojistka - na zadne platforme nesmi zbyt nic jineho nez obycejne segmenty
+    if !Path::new(&relative).components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(PathError::Traversal);
+    }
+
+    Ok(ResolvedPath {
+        full: root.join(&relative),
+        relative,
+    })
+}
+
+/// kanonicka cesta existujiciho souboru musi lezet v kanonickem koreni (symlinky
+/// ven z korene neprojdou); neexistujici cesta projde, 404 vrati handler
+pub fn contain(root: &Path, path: &Path) -> Result<(), PathError> {
+    let (root, path) = match (fs::canonicalize(root), fs::canonicalize(path)) {
+        (Ok(root), Ok(path)) => (root, path),
+        _ => {
+            return Ok(());
+        }
+    };
+
+    if path.starts_with(&root) {
+        Ok(())
+    } else {
+        Err(PathError::OutsideRoot)
+    }
+}
+
+/// prefix location odpovida jen na hranici segmentu: `/api` zachyti `/api` a `/api/x`,
+/// ale ne `/apix`; prefix koncici `/` (a `/` samotne) odpovida kazde ceste, ktera jim zacina
+pub fn prefix_matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+// `C:`, `c:foo` - na Windows by join zahodil koren
+fn is_drive_prefix(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+
+    // na Windows je `:` v nazvu vzdy podezrela (alternativni datove proudy)
+    drive || (cfg!(windows) && segment.contains(':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn root() -> PathBuf {
+        PathBuf::from("/srv/web")
+    }
+
+    fn relative(raw: &str) -> Result<String, PathError> {
+        resolve(&root(), raw).map(|resolved| resolved.relative)
+    }
+
+    // cast za korenem smi obsahovat jen obycejne segmenty
+    fn stays_in_root(root: &Path, full: &Path) -> bool {
+        match full.strip_prefix(root) {
+            Ok(rest) => rest.components().all(|c| matches!(c, Component::Normal(_))),
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn normalizes_plain_paths() {
+        assert_eq!(relative("css/app.css").as_deref(), Ok("css/app.css"));
+        assert_eq!(relative("/./css//app.css/").as_deref(), Ok("css/app.css"));
+        assert_eq!(relative("").as_deref(), Ok(""));
+        assert_eq!(relative("m%C3%A1pa%20webu.html").as_deref(), Ok("mápa webu.html"));
+        assert_eq!(resolve(&root(), "a/b").unwrap().full, root().join("a/b"));
+    }
+
+    #[test]
+    fn rejects_traversal_in_any_encoding() {
+        assert_eq!(relative(".."), Err(PathError::Traversal));
+        assert_eq!(relative("css/../../etc/passwd"), Err(PathError::Traversal));
+        assert_eq!(relative("%2e%2e/etc/passwd"), Err(PathError::Traversal));
+        assert_eq!(relative("%2E%2E%2Fetc"), Err(PathError::Traversal));
+        assert_eq!(relative("css%2f..%2f..%2fetc"), Err(PathError::Traversal));
+        assert_eq!(relative("..\\..\\windows"), Err(PathError::Traversal));
+        assert_eq!(relative("css%5c..%5cetc"), Err(PathError::Traversal));
+    }
+
+    #[test]
+    fn encoded_slash_and_backslash_are_separators() {
+        assert_eq!(relative("css%2fapp.css").as_deref(), Ok("css/app.css"));
+        assert_eq!(relative("css\\app.css").as_deref(), Ok("css/app.css"));
+        assert_eq!(relative("css%5Capp.css").as_deref(), Ok("css/app.css"));
+    }
+
+    #[test]
+    fn rejects_null_bytes_and_invalid_utf8() {
+        assert_eq!(relative("index.php%00.txt"), Err(PathError::NullByte));
+        assert_eq!(relative("a%00"), Err(PathError::NullByte));
+        assert_eq!(relative("%ff%fe"), Err(PathError::InvalidEncoding));
+    }
+
+    #[test]
+    fn rejects_drive_and_unc_prefixes() {
+        assert_eq!(relative("C:/Windows/win.ini"), Err(PathError::DrivePrefix));
+        assert_eq!(relative("c:secret"), Err(PathError::DrivePrefix));
+        assert_eq!(relative("C%3A%5CWindows"), Err(PathError::DrivePrefix));
+        assert_eq!(relative("css/D:foo"), Err(PathError::DrivePrefix));
+
+        // UNC `\\server\share` se rozpadne na obycejne segmenty uvnitr korene
+        let unc = resolve(&root(), "\\\\server\\share\\file").unwrap();
+        assert_eq!(unc.relative, "server/share/file");
+        assert!(stays_in_root(&root(), &unc.full));
+        let unc = resolve(&root(), "%5c%5cserver%5cshare").unwrap();
+        assert_eq!(unc.relative, "server/share");
+    }
+
+    #[test]
+    fn contain_rejects_symlink_outside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("web");
+        fs::create_dir(&root).unwrap();
+        fs::write(root.join("index.html"), "ok").unwrap();
+        fs::write(dir.path().join("secret.txt"), "tajne").unwrap();
+
+        assert_eq!(contain(&root, &root.join("index.html")), Ok(()));
+        // neexistujici soubor projde, 404 vrati handler
+        assert_eq!(contain(&root, &root.join("missing.html")), Ok(()));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            symlink(dir.path().join("secret.txt"), root.join("link.txt")).unwrap();
+            symlink(dir.path(), root.join("up")).unwrap();
+            symlink(root.join("index.html"), root.join("inside.html")).unwrap();
+
+            assert_eq!(contain(&root, &root.join("link.txt")), Err(PathError::OutsideRoot));
+            let through_dir = resolve(&root, "up/secret.txt").unwrap();
+            assert_eq!(contain(&root, &through_dir.full), Err(PathError::OutsideRoot));
+            assert_eq!(contain(&root, &root.join("inside.html")), Ok(()));
+        }
+    }
+
+    #[test]
+    fn prefix_matches_on_segment_boundary() {
+        assert!(prefix_matches("/api", "/api"));
+        assert!(prefix_matches("/api", "/api/users"));
+        assert!(!prefix_matches("/api", "/apix"));
+        assert!(prefix_matches("/static/", "/static/a.css"));
+        assert!(!prefix_matches("/static/", "/static"));
+        assert!(prefix_matches("/", "/cokoli"));
+    }
+
+    // kousky, ze kterych se skladaji utoky na koren
+    fn hostile_segment() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just("..".to_string()),
+            Just("%2e%2e".to_string()),
+            Just("%2E.".to_string()),
+            Just(".".to_string()),
+            Just("%2f".to_string()),
+            Just("%5c".to_string()),
+            Just("\\".to_string()),
+            Just("C:".to_string()),
+            Just("%00".to_string()),
+            Just("".to_string()),
+            "[a-z]{1,8}",
+            "[a-z]{1,4}%2[fF]\\.\\."
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn any_string_stays_under_root(raw in "\\PC*") {
+            if let Ok(resolved) = resolve(&root(), &raw) {
+                prop_assert!(stays_in_root(&root(), &resolved.full), "{:?}", resolved);
+            }
+        }
+
+        #[test]
+        fn hostile_paths_stay_under_root(
+            segments in proptest::collection::vec(hostile_segment(), 0..10),
+            separator in prop_oneof![Just("/"), Just("\\"), Just("%2f"), Just("%5C")]
+        ) {
+            let raw = segments.join(separator);
+            if let Ok(resolved) = resolve(&root(), &raw) {
+                prop_assert!(stays_in_root(&root(), &resolved.full), "{} -> {:?}", raw, resolved);
+                prop_assert!(!resolved.relative.split('/').any(|s| s == ".."));
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::time::{ Duration, Instant };
 
+#[derive(Default)]
 pub struct PerformanceMetrics {
     pub request_count: u64,
     pub total_time: Duration,
@@ -27,7 +28,7 @@ impl PerformanceMetrics {
     }
 }
 
-pub fn measure_request<F>(f: F) -> Duration where F: FnOnce() -> () {
+pub fn measure_request<F>(f: F) -> Duration where F: FnOnce() {
     let start = Instant::now();
     f();
     start.elapsed()